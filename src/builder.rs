@@ -0,0 +1,134 @@
+//! Builder to assemble an embeddable llmidium server.
+
+use std::{collections::HashMap, sync::Arc};
+
+use mcp_core::handler::TypedToolHandler;
+
+use crate::{
+    config::ListenerConfig,
+    sse::{App, subscription::SubscriptionConfig},
+    system::{SystemRouter, ToolProvider, fs::Fs, process::Process, profile::Profile},
+};
+
+pub struct LlmidiumBuilder {
+    providers: Vec<Arc<dyn ToolProvider>>,
+    profiles: HashMap<String, Arc<Profile>>,
+    subscriptions: SubscriptionConfig,
+}
+
+impl Default for LlmidiumBuilder {
+    fn default() -> Self {
+        LlmidiumBuilder {
+            providers: Vec::new(),
+            profiles: Profile::builtin()
+                .into_iter()
+                .map(|(name, profile)| (name, Arc::new(profile)))
+                .collect(),
+            subscriptions: SubscriptionConfig::default(),
+        }
+    }
+}
+
+impl LlmidiumBuilder {
+    /// Enable the `fs.*` tools and the file system resource.
+    pub fn with_fs(self, fs: Fs) -> Self {
+        self.with_tools(fs)
+    }
+
+    /// Enable the `process` tool.
+    pub fn with_process(self, process: Process) -> Self {
+        self.with_tools(process)
+    }
+
+    /// Register a single custom tool.
+    pub fn with_tool<T: TypedToolHandler + Clone + std::fmt::Debug>(self, tool: T) -> Self {
+        self.with_tools(tool)
+    }
+
+    /// Register a bundle of tools.
+    pub fn with_tools(mut self, provider: impl ToolProvider) -> Self {
+        self.providers.push(Arc::new(provider));
+        self
+    }
+
+    /// Add a profile, replacing any profile of the same name.
+    pub fn with_profile(mut self, name: impl Into<String>, profile: Profile) -> Self {
+        self.profiles.insert(name.into(), Arc::new(profile));
+        self
+    }
+
+    pub fn with_profiles(self, profiles: impl IntoIterator<Item = (String, Profile)>) -> Self {
+        profiles.into_iter().fold(self, |builder, (name, profile)| {
+            builder.with_profile(name, profile)
+        })
+    }
+
+    /// Limit the resource subscriptions of each session.
+    pub fn with_subscriptions(mut self, subscriptions: SubscriptionConfig) -> Self {
+        self.subscriptions = subscriptions;
+        self
+    }
+
+    pub fn build(self) -> Llmidium {
+        Llmidium {
+            providers: self.providers.into(),
+            profiles: self.profiles.into(),
+            subscriptions: self.subscriptions,
+        }
+    }
+}
+
+/// A configured llmidium server.
+#[derive(Debug, Clone)]
+pub struct Llmidium {
+    providers: Arc<[Arc<dyn ToolProvider>]>,
+    profiles: Arc<HashMap<String, Arc<Profile>>>,
+    subscriptions: SubscriptionConfig,
+}
+
+impl Llmidium {
+    pub fn builder() -> LlmidiumBuilder {
+        LlmidiumBuilder::default()
+    }
+
+    /// Create a [`SystemRouter`] serving the configured tools.
+    ///
+    /// Each call registers the tools anew, the sse endpoint creates one router per session.
+    pub fn system_router(&self) -> SystemRouter {
+        SystemRouter::new(self.providers.clone())
+    }
+
+    pub fn profile(&self, name: &str) -> Option<Arc<Profile>> {
+        self.profiles.get(name).cloned()
+    }
+
+    pub fn subscription_config(&self) -> SubscriptionConfig {
+        self.subscriptions.clone()
+    }
+
+    pub fn profile_names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    /// The axum router serving the mcp sse endpoint at `/sse`.
+    ///
+    /// Sessions are served all tools, or any profile they ask for with `/sse?profile=<name>`.
+    pub fn router(&self) -> axum::Router {
+        App::new(
+            self.clone(),
+            None,
+            self.profile_names().map(ToOwned::to_owned).collect(),
+        )
+        .router()
+    }
+
+    /// The axum router serving the mcp sse endpoint at `/sse` for a configured listener.
+    pub fn listener_router(&self, listener: &ListenerConfig) -> axum::Router {
+        App::new(
+            self.clone(),
+            listener.profile.clone(),
+            listener.profiles.clone(),
+        )
+        .router()
+    }
+}
@@ -1,6 +1,11 @@
-use std::net::{IpAddr, SocketAddr};
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+};
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::system::mode::Mode;
 
 /// Command line arguments for the application
 #[derive(Parser, Debug)]
@@ -17,6 +22,17 @@ pub struct CliArgs {
     /// Port to listen on
     #[arg(short, long, env, default_value_t = 300)]
     pub timeout: u32,
+
+    /// Path to the toml configuration file
+    #[arg(short, long, env)]
+    pub config: Option<PathBuf>,
+
+    /// Refuse or only report changes to the system, overrides the configuration file
+    #[arg(short, long, env, value_enum)]
+    pub mode: Option<Mode>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
 }
 
 impl CliArgs {
@@ -25,6 +41,32 @@ impl CliArgs {
     } 
 }
 
+/// Subcommands, running the server is the default when none is given
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Inspect the tools, resources and prompts served by llmidium
+    Schema {
+        #[command(subcommand)]
+        command: SchemaCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SchemaCommand {
+    /// Dump every tool, resource and prompt to stdout
+    Export {
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = SchemaFormat::Json)]
+        format: SchemaFormat,
+    },
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaFormat {
+    Json,
+    Markdown,
+}
+
 /// Parse command line arguments
 pub fn args() -> CliArgs {
     CliArgs::parse()
@@ -0,0 +1,127 @@
+//! Configuration file of the server.
+//!
+//! ```toml
+//! mode = "read_only"
+//!
+//! [fs]
+//! roots = ["/nmt/mcp-server", "/tmp"]
+//! deny = ["/etc/shadow", "/proc/*/mem"]
+//! max_read_bytes = 1048576
+//! resource_directories = ["/nmt/mcp-server/docs"]
+//!
+//! [fs.journal]
+//! max_entries = 100
+//! max_bytes = 67108864
+//! max_file_bytes = 8388608
+//!
+//! [fs.trash]
+//! enabled = true
+//! directory = "/var/lib/llmidium/trash"
+//! max_age_secs = 2592000
+//! max_bytes = 1073741824
+//!
+//! [subscriptions]
+//! max_watches = 64
+//! debounce_ms = 200
+//!
+//! [profiles.reviewer]
+//! tools = ["fs.read", "fs.list_directory", "fs.get_file_info"]
+//! tokens = ["secret"]
+//!
+//! [[listeners]]
+//! bind = "0.0.0.0:7777"
+//! profile = "dev"
+//! profiles = ["reviewer"]
+//! ```
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    sse::subscription::SubscriptionConfig,
+    system::{
+        fs::{DEFAULT_MAX_READ_BYTES, journal::JournalConfig, trash::TrashConfig},
+        mode::Mode,
+        profile::Profile,
+    },
+};
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// Whether tools which change the system are allowed, refused or only reported.
+    pub mode: Mode,
+    pub fs: FsConfig,
+    /// Limits of the resource subscriptions of each session.
+    pub subscriptions: SubscriptionConfig,
+    /// Profiles in addition to the builtin `readonly`, `dev` and `full`, which they may override.
+    pub profiles: HashMap<String, Profile>,
+    /// Addresses to listen on, the command line `--host` and `--port` are used when empty.
+    pub listeners: Vec<ListenerConfig>,
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        toml::from_str(&content)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Settings of the `fs.*` tools and the file system resource.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FsConfig {
+    /// Directories the tools are confined to, the whole file system is reachable when empty.
+    pub roots: Vec<PathBuf>,
+    /// Glob patterns of paths which can't be accessed, along with anything below them.
+    pub deny: Vec<String>,
+    /// Maximum number of bytes returned by a single `fs.read`.
+    pub max_read_bytes: u64,
+    /// Directories whose files are listed as resources.
+    pub resource_directories: Vec<PathBuf>,
+    /// Limits of the journal `fs.undo` reverts the changes of a session with.
+    pub journal: JournalConfig,
+    /// Whether and where deleted entries are kept, and for how long.
+    pub trash: TrashConfig,
+}
+
+impl Default for FsConfig {
+    fn default() -> Self {
+        FsConfig {
+            roots: Vec::new(),
+            deny: Vec::new(),
+            max_read_bytes: DEFAULT_MAX_READ_BYTES,
+            resource_directories: Vec::new(),
+            journal: JournalConfig::default(),
+            trash: TrashConfig::default(),
+        }
+    }
+}
+
+/// A listening address and the profiles its sessions may use.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ListenerConfig {
+    pub bind: SocketAddr,
+    /// Profile of sessions which don't ask for one, `full` when absent, whose tokens apply.
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Other profiles a session may ask for with `/sse?profile=<name>`.
+    #[serde(default)]
+    pub profiles: Vec<String>,
+}
+
+impl ListenerConfig {
+    pub fn new(bind: SocketAddr) -> Self {
+        ListenerConfig {
+            bind,
+            profile: None,
+            profiles: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,39 @@
+//! llmidium exposes a machine to LLM agents as an MCP server.
+//!
+//! The binary is a thin wrapper around this library, to embed llmidium in another
+//! axum service, build it with the tools you need and merge its router:
+//!
+//! ```no_run
+//! use llmidium::{Llmidium, system::{fs::Fs, process::Process}};
+//!
+//! let llmidium = Llmidium::builder()
+//!     .with_fs(Fs::default())
+//!     .with_process(Process::default())
+//!     .build();
+//! let app = axum::Router::new().nest("/llmidium", llmidium.router());
+//! # let _ = app;
+//! ```
+
+pub mod broker;
+pub mod builder;
+pub mod clap;
+pub mod config;
+pub mod schema;
+pub mod sse;
+pub mod system;
+
+pub use builder::{Llmidium, LlmidiumBuilder};
+pub use system::{SystemRouter, ToolProvider};
+
+#[macro_export]
+macro_rules! embed {
+    (
+        $path: literal
+    ) => {
+        include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/embed/",
+            $path
+        ))
+    };
+}
@@ -1,14 +1,47 @@
+use llmidium::{
+    Llmidium, clap,
+    config::Config,
+    schema,
+    system::{
+        fs::{Fs, jail::PathJail},
+        process::Process,
+    },
+};
 use tokio::io;
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
 use tracing_subscriber::EnvFilter;
-use sse::App;
 
-pub mod broker;
-pub mod system;
-pub mod sse;
-pub mod clap;
 #[tokio::main]
 async fn main() -> io::Result<()> {
+    let args = clap::args();
+    let config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    let mode = args.mode.unwrap_or(config.mode);
+    let jail = PathJail::new(&config.fs.roots, &config.fs.deny)?;
+    let llmidium = Llmidium::builder()
+        .with_fs(Fs {
+            mode,
+            jail,
+            max_read_bytes: config.fs.max_read_bytes,
+            resource_directories: config.fs.resource_directories,
+            journal: config.fs.journal,
+            trash: config.fs.trash,
+        })
+        .with_process(Process { mode })
+        .with_profiles(config.profiles)
+        .with_subscriptions(config.subscriptions)
+        .build();
+    if let Some(clap::Command::Schema {
+        command: clap::SchemaCommand::Export { format },
+    }) = &args.command
+    {
+        let schema = schema::Schema::collect(&llmidium.system_router()).await;
+        println!("{}", schema.export(*format));
+        return Ok(());
+    }
+
     // Set up file appender for logging
     // let file_appender = RollingFileAppender::new(Rotation::DAILY, "/var/log/llmidium", "server.log");
 
@@ -21,21 +54,28 @@ async fn main() -> io::Result<()> {
         .with_file(true)
         .with_line_number(true)
         .init();
-    let args = clap::args();
-    let listener = tokio::net::TcpListener::bind(args.bind()).await?;
-    tracing::debug!("listening on {}", listener.local_addr()?);
-    axum::serve(listener, App::new().router()).await
+    tracing::info!(?mode, "system mode");
+    if config.listeners.is_empty() {
+        let listener = tokio::net::TcpListener::bind(args.bind()).await?;
+        tracing::debug!("listening on {}", listener.local_addr()?);
+        return axum::serve(listener, llmidium.router()).await;
+    }
+    let mut servers = Vec::new();
+    for listener_config in &config.listeners {
+        let unknown = listener_config
+            .profile
+            .iter()
+            .chain(&listener_config.profiles)
+            .find(|name| llmidium.profile(name).is_none());
+        if let Some(name) = unknown {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("listener {} uses unknown profile {name}", listener_config.bind),
+            ));
+        }
+        let listener = tokio::net::TcpListener::bind(listener_config.bind).await?;
+        tracing::debug!(profile = ?listener_config.profile, "listening on {}", listener.local_addr()?);
+        servers.push(axum::serve(listener, llmidium.listener_router(listener_config)).into_future());
+    }
+    futures::future::try_join_all(servers).await.map(|_| ())
 }
-
-#[macro_export]
-macro_rules! embed {
-    (
-        $path: literal
-    ) => {
-        include_str!(concat!(
-            env!("CARGO_MANIFEST_DIR"),
-            "/src/embed/",
-            $path
-        ))
-    };
-}
\ No newline at end of file
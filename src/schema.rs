@@ -0,0 +1,135 @@
+//! Export of the contracts served by llmidium.
+//!
+//! The export contains every tool with its description and input schema, as well as
+//! the fixed resources, resource templates and prompts, so they can be published as
+//! documentation or diffed between releases.
+
+use std::fmt::Write;
+
+use mcp_core::{Resource, Tool, prompt::Prompt};
+use mcp_server::Router;
+use serde::Serialize;
+
+use crate::clap::SchemaFormat;
+use crate::system::{ResourceTemplate, SystemRouter};
+
+#[derive(Debug, Serialize)]
+pub struct Schema {
+    pub name: String,
+    pub version: String,
+    pub tools: Vec<Tool>,
+    pub resources: Vec<Resource>,
+    pub resource_templates: Vec<ResourceTemplate>,
+    pub prompts: Vec<Prompt>,
+}
+
+impl Schema {
+    /// Collect the schema from a router, everything is sorted by name to keep the output stable.
+    pub async fn collect(router: &SystemRouter) -> Self {
+        let mut tools = router.list_tools().await;
+        tools.sort_by(|a, b| a.name.cmp(&b.name));
+        // the resources found in the configured directories depend on the host, the
+        // templates cover them
+        let mut resources = router.resources().to_vec();
+        resources.sort_by(|a, b| a.uri.cmp(&b.uri));
+        let mut resource_templates = router.resource_templates().to_vec();
+        resource_templates.sort_by(|a, b| a.uri_template.cmp(&b.uri_template));
+        let mut prompts = router.list_prompts().await;
+        prompts.sort_by(|a, b| a.name.cmp(&b.name));
+        Schema {
+            name: router.name(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            tools,
+            resources,
+            resource_templates,
+            prompts,
+        }
+    }
+
+    pub fn export(&self, format: SchemaFormat) -> String {
+        match format {
+            SchemaFormat::Json => self.to_json(),
+            SchemaFormat::Markdown => self.to_markdown(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("invalid json")
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut md = String::new();
+        // writing to a String never fails
+        let _ = self.write_markdown(&mut md);
+        md
+    }
+
+    fn write_markdown(&self, md: &mut String) -> std::fmt::Result {
+        writeln!(md, "# {} {}", self.name, self.version)?;
+        writeln!(md)?;
+        writeln!(md, "## Tools")?;
+        for tool in &self.tools {
+            writeln!(md)?;
+            writeln!(md, "### `{}`", tool.name)?;
+            writeln!(md)?;
+            writeln!(md, "{}", tool.description.trim())?;
+            writeln!(md)?;
+            writeln!(md, "```json")?;
+            writeln!(
+                md,
+                "{}",
+                serde_json::to_string_pretty(&tool.input_schema).expect("invalid json")
+            )?;
+            writeln!(md, "```")?;
+        }
+        writeln!(md)?;
+        writeln!(md, "## Resources")?;
+        writeln!(md)?;
+        writeln!(md, "| URI | Name | MIME type | Description |")?;
+        writeln!(md, "| --- | --- | --- | --- |")?;
+        for resource in &self.resources {
+            writeln!(
+                md,
+                "| `{}` | {} | `{}` | {} |",
+                resource.uri,
+                resource.name,
+                resource.mime_type,
+                table_cell(resource.description.as_deref().unwrap_or_default()),
+            )?;
+        }
+        writeln!(md)?;
+        writeln!(md, "## Resource templates")?;
+        writeln!(md)?;
+        writeln!(md, "| URI template | Name | MIME type | Description |")?;
+        writeln!(md, "| --- | --- | --- | --- |")?;
+        for template in &self.resource_templates {
+            writeln!(
+                md,
+                "| `{}` | {} | `{}` | {} |",
+                template.uri_template,
+                template.name,
+                template.mime_type.as_deref().unwrap_or("*"),
+                table_cell(template.description.as_deref().unwrap_or_default()),
+            )?;
+        }
+        writeln!(md)?;
+        writeln!(md, "## Prompts")?;
+        writeln!(md)?;
+        writeln!(md, "| Name | Description |")?;
+        writeln!(md, "| --- | --- |")?;
+        for prompt in &self.prompts {
+            writeln!(
+                md,
+                "| `{}` | {} |",
+                prompt.name,
+                table_cell(prompt.description.as_deref().unwrap_or_default()),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Make a multi-line text fit into a single markdown table cell.
+fn table_cell(text: &str) -> String {
+    text.trim().replace('|', "\\|").replace('\n', "<br>")
+}
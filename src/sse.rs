@@ -1,7 +1,7 @@
 use axum::{
     body::Body,
     extract::{Query, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode, header::AUTHORIZATION},
     response::sse::{Event, Sse},
     routing::get,
     Router,
@@ -19,23 +19,89 @@ use tokio::{
     sync::Mutex,
 };
 use tracing_subscriber::{self};
+
+use crate::{Llmidium, system::profile::Profile};
 pub mod jsonrpc_frame_codec;
+pub mod resources;
+pub mod subscription;
+
+use mcp_core::handler::ResourceError;
+use serde_json::{Value, json};
+use subscription::Subscriptions;
 
+use crate::system::SystemRouter;
 
-type C2SWriter = Arc<Mutex<io::WriteHalf<io::SimplexStream>>>;
 type SessionId = Arc<str>;
 
+/// Code and message of a JSON-RPC error.
+type RpcError = (i64, String);
+
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+const RESOURCE_NOT_FOUND: i64 = -32002;
+
+fn resource_error(e: ResourceError) -> RpcError {
+    match e {
+        ResourceError::NotFound(message) => (RESOURCE_NOT_FOUND, message),
+        e => (INTERNAL_ERROR, e.to_string()),
+    }
+}
+
+/// What the post endpoint needs of a session.
+struct Session {
+    c2s: Mutex<io::WriteHalf<io::SimplexStream>>,
+    router: SystemRouter,
+    /// Messages for the client, sent along those of the mcp server.
+    outbox: futures::channel::mpsc::UnboundedSender<String>,
+    subscriptions: Subscriptions,
+}
+
+impl Session {
+    /// Answer the requests the mcp server can't serve, returns whether `message` was one.
+    async fn serve(&self, message: &Value) -> bool {
+        let (Some(id), Some(method)) = (message.get("id"), message["method"].as_str()) else {
+            return false;
+        };
+        let params = &message["params"];
+        let result = match self.subscriptions.handle(method, params).await {
+            Some(result) => result,
+            None => match resources::handle(&self.router, method, params).await {
+                Some(result) => result,
+                None => return false,
+            },
+        };
+        let response = match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        };
+        let _ = self.outbox.unbounded_send(response.to_string());
+        true
+    }
+}
+
 
 
-#[derive(Clone, Default)]
+#[derive(Clone)]
 pub struct App {
-    txs: Arc<tokio::sync::RwLock<HashMap<SessionId, C2SWriter>>>,
+    txs: Arc<tokio::sync::RwLock<HashMap<SessionId, Arc<Session>>>>,
+    llmidium: Llmidium,
+    profile: Option<Arc<str>>,
+    profiles: Arc<[String]>,
 }
 
 impl App {
-    pub fn new() -> Self {
+    /// Sessions use `profile`, or `full` without it, by default, or ask for one of
+    /// `profiles` with `/sse?profile=<name>`.
+    pub fn new(llmidium: Llmidium, profile: Option<String>, profiles: Vec<String>) -> Self {
         Self {
             txs: Default::default(),
+            llmidium,
+            profile: profile.map(Arc::from),
+            profiles: profiles.into(),
         }
     }
     pub fn router(&self) -> Router {
@@ -45,6 +111,36 @@ impl App {
     }
 }
 
+impl App {
+    /// Resolve the profile of a new session, a listener without a default profile serves
+    /// `full`, so that the tokens configured for it are required all the same.
+    fn authorize(
+        &self,
+        requested: Option<&str>,
+        token: Option<&str>,
+    ) -> Result<Arc<Profile>, StatusCode> {
+        let default = self.profile.as_deref().unwrap_or(Profile::FULL);
+        let name = requested.unwrap_or(default);
+        let selectable = name == default || self.profiles.iter().any(|p| p == name);
+        if !selectable {
+            return Err(StatusCode::FORBIDDEN);
+        }
+        let profile = self.llmidium.profile(name).ok_or(StatusCode::NOT_FOUND)?;
+        if !profile.authorizes(token) {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+        Ok(profile)
+    }
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
 fn session_id() -> SessionId {
     let id = format!("{:016x}", rand::random::<u128>());
     Arc::from(id)
@@ -62,56 +158,85 @@ async fn post_event_handler(
     body: Body,
 ) -> Result<StatusCode, StatusCode> {
     const BODY_BYTES_LIMIT: usize = 1 << 22;
-    let write_stream = {
+    let session = {
         let rg = app.txs.read().await;
         rg.get(session_id.as_str())
             .ok_or(StatusCode::NOT_FOUND)?
             .clone()
     };
-    let mut write_stream = write_stream.lock().await;
     let mut body = body.into_data_stream();
     if let (_, Some(size)) = body.size_hint() {
         if size > BODY_BYTES_LIMIT {
             return Err(StatusCode::PAYLOAD_TOO_LARGE);
         }
     }
-    // calculate the body size
-    let mut size = 0;
+    // read the whole body, within the limit
+    let mut message = Vec::new();
     while let Some(chunk) = body.next().await {
         let Ok(chunk) = chunk else {
             return Err(StatusCode::BAD_REQUEST);
         };
-        size += chunk.len();
-        if size > BODY_BYTES_LIMIT {
+        if message.len() + chunk.len() > BODY_BYTES_LIMIT {
             return Err(StatusCode::PAYLOAD_TOO_LARGE);
         }
-        write_stream
-            .write_all(&chunk)
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        message.extend_from_slice(&chunk);
+    }
+    // some requests are served here, everything else by the mcp server
+    let handled = match serde_json::from_slice(&message) {
+        Ok(request) => session.serve(&request).await,
+        Err(_) => false,
+    };
+    if handled {
+        return Ok(StatusCode::ACCEPTED);
     }
+    let mut write_stream = session.c2s.lock().await;
+    message.push(b'\n');
     write_stream
-        .write_u8(b'\n')
+        .write_all(&message)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(StatusCode::ACCEPTED)
 }
 
-async fn sse_handler(State(app): State<App>) -> Sse<impl Stream<Item = Result<Event, io::Error>>> {
+#[derive(Debug, serde::Deserialize)]
+pub struct SseQuery {
+    pub profile: Option<String>,
+}
+
+async fn sse_handler(
+    State(app): State<App>,
+    Query(SseQuery { profile: requested }): Query<SseQuery>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, io::Error>>>, StatusCode> {
     // it's 4KB
     const BUFFER_SIZE: usize = 1 << 12;
+    let profile = app
+        .authorize(requested.as_deref(), bearer_token(&headers))
+        .inspect_err(|status| tracing::warn!(?requested, %status, "sse connection rejected"))?;
     let session = session_id();
-    tracing::info!(%session, "sse connection");
+    tracing::info!(%session, profile = ?requested, "sse connection");
     let (c2s_read, c2s_write) = tokio::io::simplex(BUFFER_SIZE);
     let (s2c_read, s2c_write) = tokio::io::simplex(BUFFER_SIZE);
-    app.txs
-        .write()
-        .await
-        .insert(session.clone(), Arc::new(Mutex::new(c2s_write)));
+    let (outbox, outbox_read) = futures::channel::mpsc::unbounded();
+    let router = app.llmidium.system_router().with_profile(profile);
+    let subscriptions = Subscriptions::new(
+        app.llmidium.subscription_config(),
+        router.clone(),
+        outbox.clone(),
+    );
+    app.txs.write().await.insert(
+        session.clone(),
+        Arc::new(Session {
+            c2s: Mutex::new(c2s_write),
+            router: router.clone(),
+            outbox,
+            subscriptions,
+        }),
+    );
     {
         let session = session.clone();
         tokio::spawn(async move {
-            let router = RouterService(crate::system::SystemRouter::enable_all());
+            let router = RouterService(router);
             let server = Server::new(router);
             let bytes_transport = ByteTransport::new(c2s_read, s2c_write);
             let _result = server
@@ -128,14 +253,16 @@ async fn sse_handler(State(app): State<App>) -> Sse<impl Stream<Item = Result<Ev
             .event("endpoint")
             .data(format!("?sessionId={session}")),
     ))
-    .chain(
+    .chain(futures::stream::select(
         FramedRead::new(s2c_read, jsonrpc_frame_codec::JsonRpcFrameCodec)
             .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
             .and_then(move |bytes| match std::str::from_utf8(&bytes) {
                 Ok(message) => futures::future::ok(Event::default().event("message").data(message)),
                 Err(e) => futures::future::err(io::Error::new(io::ErrorKind::InvalidData, e)),
             }),
-    );
-    Sse::new(stream)
+        // answers of the session itself, and the updates of the subscribed resources
+        outbox_read.map(|message| Ok(Event::default().event("message").data(message))),
+    ));
+    Ok(Sse::new(stream))
 }
 
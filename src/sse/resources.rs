@@ -0,0 +1,161 @@
+//! `resources/list`, `resources/templates/list` and `resources/read`, served next to the
+//! mcp server, which can neither list resources page by page, nor list templates, nor
+//! read binary resources as blobs.
+
+use serde_json::{Value, json};
+
+use super::{INVALID_PARAMS, RpcError, resource_error};
+use crate::system::SystemRouter;
+
+/// Resources listed per page.
+pub const PAGE_SIZE: usize = 100;
+
+/// Serve a resource request, `None` when `method` is something else.
+pub async fn handle(
+    router: &SystemRouter,
+    method: &str,
+    params: &Value,
+) -> Option<Result<Value, RpcError>> {
+    Some(match method {
+        "resources/list" => list(router, params["cursor"].as_str()).await,
+        "resources/templates/list" => Ok(json!({
+            "resourceTemplates": router.list_resource_templates(),
+        })),
+        "resources/read" => read(router, params["uri"].as_str()).await,
+        _ => return None,
+    })
+}
+
+/// The cursor is the offset of the page in the whole listing.
+async fn list(router: &SystemRouter, cursor: Option<&str>) -> Result<Value, RpcError> {
+    let offset = match cursor {
+        Some(cursor) => cursor
+            .parse::<usize>()
+            .map_err(|_| (INVALID_PARAMS, "invalid cursor".to_string()))?,
+        None => 0,
+    };
+    let resources = router.list_all_resources().await;
+    let end = offset.saturating_add(PAGE_SIZE).min(resources.len());
+    let mut result = json!({
+        "resources": resources.get(offset..end).unwrap_or_default(),
+    });
+    if end < resources.len() {
+        result["nextCursor"] = end.to_string().into();
+    }
+    Ok(result)
+}
+
+async fn read(router: &SystemRouter, uri: Option<&str>) -> Result<Value, RpcError> {
+    let uri = uri.ok_or_else(|| (INVALID_PARAMS, "missing resource uri".to_string()))?;
+    let contents = router
+        .read_resource_contents(uri)
+        .await
+        .map_err(resource_error)?;
+    Ok(json!({ "contents": [contents] }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{
+        super::{INTERNAL_ERROR, RESOURCE_NOT_FOUND},
+        *,
+    };
+    use crate::system::{
+        fs::{Fs, resource, testing::Scratch},
+        profile::Profile,
+    };
+
+    fn serve(dir: &Scratch, profile: Profile) -> SystemRouter {
+        let fs = Fs {
+            resource_directories: vec![dir.to_path_buf()],
+            ..Fs::default()
+        };
+        SystemRouter::new(Arc::new([Arc::new(fs)])).with_profile(Arc::new(profile))
+    }
+
+    #[tokio::test]
+    async fn hides_the_files_from_profiles_which_cant_read_them() {
+        let dir = Scratch::new("resources-profile");
+        let uri = resource::uri(&dir.write("file", "text"));
+        let params = json!({ "uri": uri });
+
+        let router = serve(&dir, Profile::new(["fs.list_directory"]));
+        let (code, _) = handle(&router, "resources/read", &params)
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(code, RESOURCE_NOT_FOUND);
+        let listed = handle(&router, "resources/list", &json!({})).await.unwrap();
+        assert_eq!(listed.unwrap()["resources"], json!([]));
+        let templates = handle(&router, "resources/templates/list", &json!({}))
+            .await
+            .unwrap();
+        assert_eq!(templates.unwrap()["resourceTemplates"], json!([]));
+
+        let router = serve(&dir, Profile::new(["fs.read"]));
+        let read = handle(&router, "resources/read", &params).await.unwrap();
+        assert_eq!(read.unwrap()["contents"][0]["text"], "text");
+    }
+
+    #[tokio::test]
+    async fn reads_text_as_text_and_binary_as_blobs() {
+        let dir = Scratch::new("resources-read");
+        let text = resource::uri(&dir.write("text.txt", "text"));
+        let binary = resource::uri(&dir.write("binary", [0, 1, 2]));
+        let large = resource::uri(&dir.write("large", [b'a'; 11]));
+        let fs = Fs {
+            max_read_bytes: 10,
+            ..Fs::default()
+        };
+        let router = SystemRouter::new(Arc::new([Arc::new(fs)]));
+        let read = async |uri: &str| {
+            handle(&router, "resources/read", &json!({ "uri": uri }))
+                .await
+                .unwrap()
+        };
+
+        let contents = &read(&text).await.unwrap()["contents"][0];
+        assert_eq!(contents["text"], "text");
+        assert!(contents.get("blob").is_none());
+        let contents = &read(&binary).await.unwrap()["contents"][0];
+        assert_eq!(contents["blob"], "AAEC");
+        assert!(contents.get("text").is_none());
+        let (code, message) = read(&large).await.unwrap_err();
+        assert_eq!(code, INTERNAL_ERROR, "{message}");
+        assert!(message.contains("11 bytes"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn lists_page_by_page() {
+        let dir = Scratch::new("resources-list");
+        for i in 0..PAGE_SIZE + 1 {
+            dir.write(&format!("{i:03}"), "");
+        }
+        let router = serve(&dir, Profile::full());
+        let list = async |params: Value| {
+            handle(&router, "resources/list", &params)
+                .await
+                .unwrap()
+                .unwrap()
+        };
+
+        let first = list(json!({})).await;
+        assert_eq!(first["resources"].as_array().unwrap().len(), PAGE_SIZE);
+        assert_eq!(first["resources"][0]["name"], "000");
+        assert_eq!(first["nextCursor"], PAGE_SIZE.to_string());
+        let second = list(json!({ "cursor": first["nextCursor"] })).await;
+        let resources = second["resources"].as_array().unwrap();
+        assert_eq!(resources.len(), 1);
+        assert_eq!(resources[0]["uri"], resource::uri(&dir.join("100")));
+        assert_eq!(resources[0]["size"], 0);
+        assert!(second.get("nextCursor").is_none());
+        assert!(
+            handle(&router, "resources/list", &json!({ "cursor": "x" }))
+                .await
+                .unwrap()
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,379 @@
+//! `resources/subscribe` and `resources/unsubscribe`, served next to the mcp server.
+//!
+//! The mcp server can't send notifications of its own, so the sse endpoint serves these
+//! two requests: each session watches the files behind the resources it subscribed to,
+//! and sends `notifications/resources/updated` once the changes of a resource settle.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures::{
+    StreamExt,
+    channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+};
+use notify::{EventKind, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::time::Instant;
+
+use super::{INTERNAL_ERROR, INVALID_PARAMS, RpcError, resource_error};
+use crate::system::SystemRouter;
+
+/// Default maximum number of resources a session may subscribe to.
+pub const DEFAULT_MAX_WATCHES: usize = 64;
+/// Default time a resource must stay unchanged before it's reported updated.
+pub const DEFAULT_DEBOUNCE_MS: u64 = 200;
+
+/// Limits of the resource subscriptions of a session.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SubscriptionConfig {
+    /// Maximum number of resources a session may subscribe to at once.
+    pub max_watches: usize,
+    /// Milliseconds a resource must stay unchanged before it's reported updated, so that a
+    /// burst of writes is reported once.
+    pub debounce_ms: u64,
+}
+
+impl Default for SubscriptionConfig {
+    fn default() -> Self {
+        SubscriptionConfig {
+            max_watches: DEFAULT_MAX_WATCHES,
+            debounce_ms: DEFAULT_DEBOUNCE_MS,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Subscription {
+    path: PathBuf,
+    /// A directory is updated when any of its entries changes.
+    dir: bool,
+}
+
+impl Subscription {
+    /// The directory watched for the subscription, the parent of a file so that the file
+    /// is still followed after it's replaced by a rename.
+    fn watched(&self) -> &Path {
+        if self.dir {
+            &self.path
+        } else {
+            self.path.parent().unwrap_or(Path::new("/"))
+        }
+    }
+
+    fn is_affected_by(&self, path: &Path) -> bool {
+        path == self.path || (self.dir && path.parent() == Some(&self.path))
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    subscriptions: HashMap<String, Subscription>,
+    /// Number of subscriptions watching each directory.
+    watched: HashMap<PathBuf, usize>,
+}
+
+/// File system events of the watched directories.
+type Events = notify::Result<notify::Event>;
+
+/// The resource subscriptions of a session.
+pub struct Subscriptions {
+    config: SubscriptionConfig,
+    router: SystemRouter,
+    /// Created on the first subscription, most sessions never subscribe and shouldn't use
+    /// up one of the inotify instances of the user.
+    watcher: Mutex<Option<notify::RecommendedWatcher>>,
+    events: UnboundedSender<Events>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Subscriptions {
+    /// Updates are sent to `outbox`, watches end when the subscriptions are dropped, along
+    /// with the session.
+    pub fn new(
+        config: SubscriptionConfig,
+        router: SystemRouter,
+        outbox: UnboundedSender<String>,
+    ) -> Self {
+        let (events, events_rx) = mpsc::unbounded();
+        let state = Arc::new(Mutex::new(State::default()));
+        tokio::spawn(notify_updates(
+            events_rx,
+            state.clone(),
+            outbox,
+            Duration::from_millis(config.debounce_ms),
+        ));
+        Subscriptions {
+            config,
+            router,
+            watcher: Mutex::new(None),
+            events,
+            state,
+        }
+    }
+
+    /// Serve a subscription request, `None` when `method` is something else.
+    pub async fn handle(&self, method: &str, params: &Value) -> Option<Result<Value, RpcError>> {
+        let uri = params["uri"].as_str();
+        let result = match (method, uri) {
+            ("resources/subscribe", Some(uri)) => self.subscribe(uri).await,
+            ("resources/unsubscribe", Some(uri)) => self.unsubscribe(uri),
+            ("resources/subscribe" | "resources/unsubscribe", None) => {
+                Err((INVALID_PARAMS, "missing resource uri".to_string()))
+            }
+            _ => return None,
+        };
+        Some(result.map(|()| json!({})))
+    }
+
+    async fn subscribe(&self, uri: &str) -> Result<(), RpcError> {
+        let path = self
+            .router
+            .resource_path(uri)
+            .await
+            .map_err(resource_error)?;
+        let dir = tokio::fs::metadata(&path)
+            .await
+            .is_ok_and(|metadata| metadata.is_dir());
+        let subscription = Subscription { path, dir };
+
+        let mut state = self.state.lock().expect("poisoned");
+        if state.subscriptions.contains_key(uri) {
+            return Ok(());
+        }
+        if state.subscriptions.len() >= self.config.max_watches {
+            return Err((
+                INVALID_PARAMS,
+                format!(
+                    "at most {} resources can be subscribed to at once",
+                    self.config.max_watches
+                ),
+            ));
+        }
+        let watched = subscription.watched().to_path_buf();
+        if !state.watched.contains_key(&watched) {
+            let mut watcher = self.watcher.lock().expect("poisoned");
+            let watcher = match &mut *watcher {
+                Some(watcher) => watcher,
+                None => {
+                    let events = self.events.clone();
+                    let created = notify::recommended_watcher(move |event| {
+                        let _ = events.unbounded_send(event);
+                    })
+                    .map_err(|e| (INTERNAL_ERROR, format!("can't watch files: {e}")))?;
+                    watcher.insert(created)
+                }
+            };
+            watcher
+                .watch(&watched, RecursiveMode::NonRecursive)
+                .map_err(|e| (INTERNAL_ERROR, format!("can't watch {uri}: {e}")))?;
+        }
+        *state.watched.entry(watched).or_default() += 1;
+        state.subscriptions.insert(uri.to_string(), subscription);
+        tracing::debug!(%uri, "resource subscribed");
+        Ok(())
+    }
+
+    /// Unsubscribing from a resource which isn't subscribed to does nothing.
+    fn unsubscribe(&self, uri: &str) -> Result<(), RpcError> {
+        let mut state = self.state.lock().expect("poisoned");
+        let Some(subscription) = state.subscriptions.remove(uri) else {
+            return Ok(());
+        };
+        let watched = subscription.watched();
+        let Some(count) = state.watched.get_mut(watched) else {
+            return Ok(());
+        };
+        *count -= 1;
+        if *count == 0 {
+            state.watched.remove(watched);
+            // the directory may be gone already, along with its watch
+            if let Some(watcher) = self.watcher.lock().expect("poisoned").as_mut() {
+                let _ = watcher.unwatch(watched);
+            }
+        }
+        tracing::debug!(%uri, "resource unsubscribed");
+        Ok(())
+    }
+}
+
+/// Report the resources affected by file system events once no event affected them for the
+/// debounce delay, until the subscriptions are dropped.
+async fn notify_updates(
+    mut events: UnboundedReceiver<Events>,
+    state: Arc<Mutex<State>>,
+    outbox: UnboundedSender<String>,
+    delay: Duration,
+) {
+    let mut debouncer = Debouncer::new(delay);
+    loop {
+        let next = debouncer.next();
+        tokio::select! {
+            event = events.next() => {
+                let Some(event) = event else {
+                    break;
+                };
+                let event = match event {
+                    Ok(event) => event,
+                    Err(e) => {
+                        tracing::warn!(?e, "watch error");
+                        continue;
+                    }
+                };
+                // reads don't change anything, and reading an updated resource must not
+                // report it again
+                if matches!(event.kind, EventKind::Access(_)) {
+                    continue;
+                }
+                let now = Instant::now();
+                let state = state.lock().expect("poisoned");
+                for (uri, subscription) in &state.subscriptions {
+                    if event.paths.iter().any(|path| subscription.is_affected_by(path)) {
+                        debouncer.changed(uri, now);
+                    }
+                }
+            }
+            _ = tokio::time::sleep_until(next.unwrap_or_else(Instant::now)), if next.is_some() => {
+                for uri in debouncer.settled(Instant::now()) {
+                    let notification = json!({
+                        "jsonrpc": "2.0",
+                        "method": "notifications/resources/updated",
+                        "params": { "uri": uri },
+                    });
+                    let _ = outbox.unbounded_send(notification.to_string());
+                }
+            }
+        }
+    }
+}
+
+/// The resources changed lately, each one reported once it stays unchanged for the delay.
+#[derive(Debug)]
+struct Debouncer {
+    delay: Duration,
+    /// When each changed resource is due to be reported.
+    pending: HashMap<String, Instant>,
+}
+
+impl Debouncer {
+    fn new(delay: Duration) -> Self {
+        Debouncer {
+            delay,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Every change pushes the report back, until the resource settles.
+    fn changed(&mut self, uri: &str, now: Instant) {
+        self.pending.insert(uri.to_string(), now + self.delay);
+    }
+
+    /// When the next report is due.
+    fn next(&self) -> Option<Instant> {
+        self.pending.values().min().copied()
+    }
+
+    /// The resources which settled by `now`, no longer pending.
+    fn settled(&mut self, now: Instant) -> Vec<String> {
+        let mut settled = Vec::new();
+        self.pending.retain(|uri, deadline| {
+            if *deadline > now {
+                return true;
+            }
+            settled.push(uri.clone());
+            false
+        });
+        settled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::ModifyKind;
+
+    use super::*;
+    use crate::system::fs::{Fs, resource, testing::Scratch};
+
+    fn subscriptions(
+        max_watches: usize,
+        debounce_ms: u64,
+    ) -> (Subscriptions, UnboundedReceiver<String>) {
+        let (outbox, updates) = mpsc::unbounded();
+        let router = SystemRouter::new(Arc::new([Arc::new(Fs::default())]));
+        let config = SubscriptionConfig {
+            max_watches,
+            debounce_ms,
+        };
+        (Subscriptions::new(config, router, outbox), updates)
+    }
+
+    #[test]
+    fn reports_a_resource_once_it_settles() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut debouncer = Debouncer::new(Duration::from_millis(100));
+        debouncer.changed("a", at(0));
+        debouncer.changed("a", at(50));
+        debouncer.changed("b", at(60));
+        assert_eq!(debouncer.next(), Some(at(150)));
+        assert!(debouncer.settled(at(149)).is_empty());
+        assert_eq!(debouncer.settled(at(150)), ["a"]);
+        assert_eq!(debouncer.next(), Some(at(160)));
+        assert_eq!(debouncer.settled(at(1000)), ["b"]);
+        assert_eq!(debouncer.next(), None);
+    }
+
+    #[tokio::test]
+    async fn notifies_a_burst_of_changes_once() {
+        let dir = Scratch::new("subscribe-burst");
+        let path = dir.write("file", "");
+        let uri = resource::uri(&path);
+        let (subscriptions, mut updates) = subscriptions(DEFAULT_MAX_WATCHES, 20);
+        subscriptions.subscribe(&uri).await.unwrap();
+        for kind in [
+            EventKind::Modify(ModifyKind::Any),
+            EventKind::Access(notify::event::AccessKind::Any),
+            EventKind::Modify(ModifyKind::Any),
+        ] {
+            let event = notify::Event::new(kind).add_path(path.clone());
+            subscriptions.events.unbounded_send(Ok(event)).unwrap();
+        }
+        let update = tokio::time::timeout(Duration::from_secs(5), updates.next())
+            .await
+            .unwrap()
+            .unwrap();
+        let update: Value = serde_json::from_str(&update).unwrap();
+        assert_eq!(update["method"], "notifications/resources/updated");
+        assert_eq!(update["params"]["uri"], uri);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(updates.try_next().is_err(), "notified more than once");
+    }
+
+    #[tokio::test]
+    async fn limits_the_watches_and_drops_them_on_unsubscribe() {
+        let dir = Scratch::new("subscribe-limit");
+        let a = resource::uri(&dir.write("a", ""));
+        let b = resource::uri(&dir.write("sub/b", ""));
+        let (subscriptions, _updates) = subscriptions(1, DEFAULT_DEBOUNCE_MS);
+        subscriptions.subscribe(&a).await.unwrap();
+        // subscribing again doesn't count
+        subscriptions.subscribe(&a).await.unwrap();
+        let (code, _) = subscriptions.subscribe(&b).await.unwrap_err();
+        assert_eq!(code, INVALID_PARAMS);
+
+        subscriptions.unsubscribe(&a).unwrap();
+        {
+            let state = subscriptions.state.lock().unwrap();
+            assert!(state.subscriptions.is_empty());
+            assert!(state.watched.is_empty());
+        }
+        subscriptions.subscribe(&b).await.unwrap();
+        let state = subscriptions.state.lock().unwrap();
+        assert_eq!(state.watched.keys().collect::<Vec<_>>(), [&dir.join("sub")]);
+    }
+}
@@ -1,38 +1,210 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
+use futures::future::BoxFuture;
 use mcp_core::{
-    Content, Resource, Tool, ToolError,
-    handler::{PromptError, ResourceError},
+    Content, Resource, ResourceContents, Tool, ToolError,
+    handler::{PromptError, ResourceError, TypedToolHandler},
     prompt::Prompt,
     protocol::{PromptsCapability, ResourcesCapability, ServerCapabilities, ToolsCapability},
     toolset::ToolSet,
 };
 use mcp_server::Router;
+use serde::Serialize;
 use serde_json::Value;
 
 use crate::embed;
 pub mod fs;
+pub mod mode;
 pub mod process;
+pub mod profile;
 pub mod prompt;
 pub mod broker;
 
+/// A bundle of tools, and the resources backing them, which can be served by a [`SystemRouter`].
+///
+/// Every [`TypedToolHandler`] which is `Clone` and `Debug` is a provider of itself.
+pub trait ToolProvider: std::fmt::Debug + Send + Sync + 'static {
+    fn register(&self, tool_set: &mut ToolSet);
+    fn resources(&self) -> Vec<Resource> {
+        Vec::new()
+    }
+    fn resource_templates(&self) -> Vec<ResourceTemplate> {
+        Vec::new()
+    }
+    /// Resources found when they are listed, such as the files of a directory, in a stable
+    /// order so that they can be listed page by page.
+    fn list_resources(&self) -> BoxFuture<'_, Vec<ListedResource>> {
+        Box::pin(futures::future::ready(Vec::new()))
+    }
+    /// Read one of the resources of this provider, `None` when `uri` is not one of them.
+    fn read_resource<'a>(
+        &'a self,
+        _uri: &'a str,
+    ) -> Option<BoxFuture<'a, Result<ResourceContents, ResourceError>>> {
+        None
+    }
+    /// The file or directory behind one of the resources of this provider, watched for
+    /// changes once the resource is subscribed to, `None` when `uri` is not one of them.
+    fn resource_path<'a>(
+        &'a self,
+        _uri: &'a str,
+    ) -> Option<BoxFuture<'a, Result<PathBuf, ResourceError>>> {
+        None
+    }
+    /// The tool a profile has to allow for its sessions to list, read and subscribe to the
+    /// resources of this provider, `None` when they are served to every session.
+    fn resource_tool(&self) -> Option<&'static str> {
+        None
+    }
+}
+
+impl<T: TypedToolHandler + Clone + std::fmt::Debug> ToolProvider for T {
+    fn register(&self, tool_set: &mut ToolSet) {
+        tool_set.add_tool(self.clone());
+    }
+}
+
+/// A pattern of resource uris, served by `resources/templates/list`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceTemplate {
+    /// An RFC 6570 uri template, such as `file://llmidium/{path}`.
+    pub uri_template: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// MIME type of all the matching resources, when they share one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+/// A resource served by `resources/list`, along with its size in bytes when it's known.
+#[derive(Debug, Clone, Serialize)]
+pub struct ListedResource {
+    #[serde(flatten)]
+    pub resource: Resource,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+}
+
+impl From<Resource> for ListedResource {
+    fn from(resource: Resource) -> Self {
+        ListedResource {
+            resource,
+            size: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SystemRouter {
+    providers: Arc<[Arc<dyn ToolProvider>]>,
     tool_set: Arc<ToolSet>,
     resource: Arc<[Resource]>,
+    resource_templates: Arc<[ResourceTemplate]>,
     prompt: Arc<[Prompt]>,
+    profile: Option<Arc<profile::Profile>>,
 }
 
 impl SystemRouter {
-    pub fn enable_all() -> Self {
+    pub fn new(providers: Arc<[Arc<dyn ToolProvider>]>) -> Self {
         let mut tool_set = ToolSet::default();
-        tool_set.add_tool(process::Process::default());
-        tool_set.extend(fs::toolset());
+        let mut resource = Vec::new();
+        let mut resource_templates = Vec::new();
+        for provider in providers.iter() {
+            provider.register(&mut tool_set);
+            resource.extend(provider.resources());
+            resource_templates.extend(provider.resource_templates());
+        }
         SystemRouter {
+            providers,
             tool_set: tool_set.into(),
-            resource: Arc::from(fs::resource_set()),
+            resource: resource.into(),
+            resource_templates: resource_templates.into(),
             prompt: prompt::prompts().into(),
+            profile: None,
+        }
+    }
+
+    /// Only serve the tools allowed by `profile`, and the resources backed by them.
+    pub fn with_profile(mut self, profile: Arc<profile::Profile>) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// The fixed resources, without those the providers find when they are listed.
+    pub fn resources(&self) -> &[Resource] {
+        &self.resource
+    }
+
+    pub fn resource_templates(&self) -> &[ResourceTemplate] {
+        &self.resource_templates
+    }
+
+    /// The templates of the resources served to this session.
+    pub fn list_resource_templates(&self) -> Vec<ResourceTemplate> {
+        self.resource_providers()
+            .flat_map(|provider| provider.resource_templates())
+            .collect()
+    }
+
+    /// Every resource served to this session, the fixed ones first, then those the
+    /// providers find.
+    pub async fn list_all_resources(&self) -> Vec<ListedResource> {
+        let mut resources: Vec<ListedResource> = self
+            .resource_providers()
+            .flat_map(|provider| provider.resources())
+            .map(Into::into)
+            .collect();
+        for provider in self.resource_providers() {
+            resources.extend(provider.list_resources().await);
         }
+        resources
+    }
+
+    pub async fn read_resource_contents(
+        &self,
+        uri: &str,
+    ) -> Result<ResourceContents, ResourceError> {
+        for provider in self.resource_providers() {
+            if let Some(contents) = provider.read_resource(uri) {
+                return contents.await;
+            }
+        }
+        Err(ResourceError::NotFound("no such resource".into()))
+    }
+
+    /// The file or directory behind a resource, to watch it for changes.
+    pub async fn resource_path(&self, uri: &str) -> Result<PathBuf, ResourceError> {
+        for provider in self.resource_providers() {
+            if let Some(path) = provider.resource_path(uri) {
+                return path.await;
+            }
+        }
+        Err(ResourceError::NotFound("no such resource".into()))
+    }
+
+    fn allows(&self, tool_name: &str) -> bool {
+        self.profile
+            .as_ref()
+            .is_none_or(|profile| profile.allows(tool_name))
+    }
+
+    /// The providers whose resources the profile lets this session see, the others are
+    /// served as if they had none.
+    fn resource_providers(&self) -> impl Iterator<Item = &Arc<dyn ToolProvider>> {
+        self.providers.iter().filter(|provider| {
+            provider
+                .resource_tool()
+                .is_none_or(|tool_name| self.allows(tool_name))
+        })
+    }
+
+    pub fn enable_all() -> Self {
+        Self::new(Arc::new([
+            Arc::new(process::Process::default()),
+            Arc::new(fs::Fs::default()),
+        ]))
     }
 }
 
@@ -61,7 +233,9 @@ impl Router for SystemRouter {
     }
 
     async fn list_tools(&self) -> Vec<Tool> {
-        self.tool_set.list_all()
+        let mut tools = self.tool_set.list_all();
+        tools.retain(|tool| self.allows(&tool.name));
+        tools
     }
 
     async fn call_tool(
@@ -69,20 +243,28 @@ impl Router for SystemRouter {
         tool_name: &str,
         arguments: Value,
     ) -> Result<Vec<Content>, ToolError> {
+        if !self.allows(tool_name) {
+            return Err(ToolError::NotFound(format!(
+                "{tool_name} is not enabled in this session"
+            )));
+        }
         self.tool_set.call(tool_name, arguments).await
     }
 
     async fn list_resources(&self) -> Vec<Resource> {
-        self.resource.to_vec()
+        self.list_all_resources()
+            .await
+            .into_iter()
+            .map(|listed| listed.resource)
+            .collect()
     }
 
+    /// Binary contents are read as base64, the sse endpoint serves them as blobs.
     async fn read_resource(&self, uri: &str) -> Result<String, ResourceError> {
-        if let Some(path) = uri.strip_prefix(fs::FS_RESOURCE) {
-            return tokio::fs::read_to_string(path)
-                .await
-                .map_err(ResourceError::execution);
+        match self.read_resource_contents(uri).await? {
+            ResourceContents::TextResourceContents { text, .. } => Ok(text),
+            ResourceContents::BlobResourceContents { blob, .. } => Ok(blob),
         }
-        Err(ResourceError::NotFound("no such resource".into()))
     }
 
     async fn list_prompts(&self) -> Vec<Prompt> {
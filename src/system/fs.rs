@@ -3,58 +3,255 @@
 //! This module provides a structured interface for various file system operations
 //! including reading, writing, creating, and manipulating files and directories.
 
-use chrono::Utc;
-use mcp_core::handler::TypedToolHandler;
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use mcp_core::handler::{ResourceError, TypedToolHandler};
 use mcp_core::{Content, toolset::ToolSet};
-use mcp_core::{Resource, ToolError};
+use mcp_core::{ResourceContents, ToolError};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::os::unix::fs::PermissionsExt;
-use std::time::UNIX_EPOCH;
+use serde_json::json;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::system::mode::{self, Mode};
+use crate::system::{ListedResource, ResourceTemplate};
+use crate::{ToolProvider, embed};
+
+pub mod atomic;
+pub mod copy;
+pub mod diff;
+pub mod edit;
+pub mod encoding;
+pub mod glob;
+pub mod info;
+pub mod jail;
+pub mod journal;
+pub mod link;
+pub mod list;
+pub mod patch;
+pub mod read_lines;
+pub mod rename;
+pub mod resource;
+pub mod search;
+#[cfg(test)]
+pub(crate) mod testing;
+pub mod transaction;
+pub mod trash;
+pub mod walk;
+
+use encoding::Encoding;
+use jail::PathJail;
+use journal::{JournalConfig, Target};
+pub use resource::FS_RESOURCE;
+use trash::{Trash, TrashConfig};
+
+/// Default maximum number of bytes returned by a single `fs.read`, 1 MiB.
+pub const DEFAULT_MAX_READ_BYTES: u64 = 1 << 20;
+
+/// The `fs.*` tools and the file system resource.
+#[derive(Debug, Clone)]
+pub struct Fs {
+    pub mode: Mode,
+    pub jail: PathJail,
+    /// Maximum number of bytes returned by a single `fs.read`.
+    pub max_read_bytes: u64,
+    /// Directories whose files are listed as resources.
+    pub resource_directories: Vec<PathBuf>,
+    /// Limits of the journal of the changes of each session.
+    pub journal: JournalConfig,
+    /// Where deleted entries go, when they aren't removed for good.
+    pub trash: TrashConfig,
+}
+
+impl Default for Fs {
+    fn default() -> Self {
+        Fs {
+            mode: Mode::default(),
+            jail: PathJail::default(),
+            max_read_bytes: DEFAULT_MAX_READ_BYTES,
+            resource_directories: Vec::new(),
+            journal: JournalConfig::default(),
+            trash: TrashConfig::default(),
+        }
+    }
+}
+
+impl ToolProvider for Fs {
+    fn register(&self, tool_set: &mut ToolSet) {
+        tool_set.extend(toolset(self));
+    }
+
+    fn resource_templates(&self) -> Vec<ResourceTemplate> {
+        resource::resource_templates()
+    }
+
+    /// The files behind the resources are those `fs.read` reads.
+    fn resource_tool(&self) -> Option<&'static str> {
+        Some("fs.read")
+    }
+
+    fn list_resources(&self) -> BoxFuture<'_, Vec<ListedResource>> {
+        let jail = self.jail.clone();
+        let directories = self.resource_directories.clone();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || resource::list(&jail, &directories))
+                .await
+                .unwrap_or_default()
+        })
+    }
+
+    fn read_resource<'a>(
+        &'a self,
+        uri: &'a str,
+    ) -> Option<BoxFuture<'a, Result<ResourceContents, ResourceError>>> {
+        uri.starts_with(FS_RESOURCE).then(|| {
+            Box::pin(resource::read(&self.jail, self.max_read_bytes, uri)) as BoxFuture<'a, _>
+        })
+    }
 
-use crate::embed;
+    fn resource_path<'a>(
+        &'a self,
+        uri: &'a str,
+    ) -> Option<BoxFuture<'a, Result<PathBuf, ResourceError>>> {
+        let path = uri.strip_prefix(FS_RESOURCE)?;
+        Some(Box::pin(async move {
+            let path = self
+                .jail
+                .resolve(path)
+                .await
+                .map_err(ResourceError::execution)?;
+            std::path::absolute(path).map_err(ResourceError::execution)
+        }))
+    }
+}
+
+/// State shared by the `fs.*` tools of a session.
+#[derive(Debug)]
+pub struct FsContext {
+    pub mode: Mode,
+    pub jail: PathJail,
+    pub max_read_bytes: u64,
+    /// The changes made by the tools of the session, to undo them.
+    pub journal: journal::Journal,
+    /// The trash deleted entries are moved to, `None` when they are removed for good.
+    pub trash: Option<Trash>,
+}
+
+impl FsContext {
+    pub fn new(fs: &Fs) -> Self {
+        FsContext {
+            mode: fs.mode,
+            jail: fs.jail.clone(),
+            max_read_bytes: fs.max_read_bytes,
+            journal: journal::Journal::new(fs.journal.clone()),
+            trash: fs.trash.enabled.then(|| Trash::new(fs.trash.clone())),
+        }
+    }
+
+    /// Resolve a path given to a tool within the jail, following symlinks.
+    pub async fn path(&self, path: &str) -> mcp_core::ToolResult<PathBuf> {
+        self.jail.resolve(path).await.map_err(ToolError::execution)
+    }
+
+    /// Resolve the path of an entry which is operated on itself, even when it's a symlink.
+    pub async fn entry(&self, path: &str) -> mcp_core::ToolResult<PathBuf> {
+        self.jail
+            .resolve_entry(path)
+            .await
+            .map_err(ToolError::execution)
+    }
+}
 
-pub fn toolset() -> ToolSet {
+pub fn toolset(fs: &Fs) -> ToolSet {
+    let ctx = Arc::new(FsContext::new(fs));
     let mut tool_set = ToolSet::default();
-    tool_set.add_tool(FsRead);
-    tool_set.add_tool(FsWrite);
-    tool_set.add_tool(FsCreate);
-    tool_set.add_tool(FsDelete);
-    tool_set.add_tool(FsRename);
-    tool_set.add_tool(FsMove);
-    tool_set.add_tool(FsCopy);
-    tool_set.add_tool(FsListDirectory);
-    tool_set.add_tool(FsMakeDirectory);
-    tool_set.add_tool(FsRemoveDirectory);
-    tool_set.add_tool(FsGetFileInfo);
-    tool_set.add_tool(FsSetPermissions);
-    tool_set.add_tool(FsChangeOwnership);
+    tool_set.add_tool(FsRead { ctx: ctx.clone() });
+    tool_set.add_tool(read_lines::FsReadLines { ctx: ctx.clone() });
+    tool_set.add_tool(search::FsSearch { ctx: ctx.clone() });
+    tool_set.add_tool(glob::FsGlob { ctx: ctx.clone() });
+    tool_set.add_tool(diff::FsDiff { ctx: ctx.clone() });
+    tool_set.add_tool(FsWrite { ctx: ctx.clone() });
+    tool_set.add_tool(edit::FsEdit { ctx: ctx.clone() });
+    tool_set.add_tool(patch::FsApplyPatch { ctx: ctx.clone() });
+    tool_set.add_tool(transaction::FsTransaction { ctx: ctx.clone() });
+    tool_set.add_tool(FsCreate { ctx: ctx.clone() });
+    tool_set.add_tool(FsDelete { ctx: ctx.clone() });
+    tool_set.add_tool(rename::FsRename { ctx: ctx.clone() });
+    tool_set.add_tool(rename::FsMove { ctx: ctx.clone() });
+    tool_set.add_tool(copy::FsCopy { ctx: ctx.clone() });
+    tool_set.add_tool(link::FsSymlink { ctx: ctx.clone() });
+    tool_set.add_tool(link::FsHardlink { ctx: ctx.clone() });
+    tool_set.add_tool(link::FsReadLink { ctx: ctx.clone() });
+    tool_set.add_tool(list::FsListDirectory { ctx: ctx.clone() });
+    tool_set.add_tool(FsMakeDirectory { ctx: ctx.clone() });
+    tool_set.add_tool(FsRemoveDirectory { ctx: ctx.clone() });
+    tool_set.add_tool(info::FsGetFileInfo { ctx: ctx.clone() });
+    tool_set.add_tool(FsSetPermissions { ctx: ctx.clone() });
+    tool_set.add_tool(FsChangeOwnership { ctx: ctx.clone() });
+    tool_set.add_tool(journal::FsHistory { ctx: ctx.clone() });
+    tool_set.add_tool(journal::FsUndo { ctx: ctx.clone() });
+    tool_set.add_tool(trash::FsTrashList { ctx: ctx.clone() });
+    tool_set.add_tool(trash::FsTrashRestore { ctx: ctx.clone() });
+    tool_set.add_tool(trash::FsTrashEmpty { ctx });
     tool_set
 }
-pub const FS_RESOURCE: &str = "file://llmidium";
 
-pub fn resource_set() -> Vec<Resource> {
-    vec![Resource {
-        uri: FS_RESOURCE.to_string(),
-        name: "fs".to_string(),
-        description: Some(embed!("resources/fs").to_owned()),
-        mime_type: "blob".to_string(),
-        annotations: None,
-    }]
+/// Count the entries and bytes below `path`, as a dry run of removing it.
+fn disk_usage(path: &Path) -> std::io::Result<(u64, u64)> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    let (mut entries, mut bytes) = (1, metadata.len());
+    if metadata.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            let (sub_entries, sub_bytes) = disk_usage(&entry?.path())?;
+            entries += sub_entries;
+            bytes += sub_bytes;
+        }
+    }
+    Ok((entries, bytes))
 }
-
 /// Parameters for reading from a file.
 #[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct ReadParams {
     /// Path to the file to be read.
     pub path: String,
-    /// Starting position (in bytes) from which to read.
-    pub offset: Option<u32>,
-    /// Number of bytes to read.
-    pub length: Option<u32>,
+    /// Starting position (in bytes) from which to read, to continue a read which returned
+    /// `has_more`, pass its `next_offset`.
+    pub offset: Option<u64>,
+    /// Number of bytes to read, capped by the server's maximum read size.
+    pub length: Option<u64>,
+    /// How to return the content, binary content is returned in `base64` and text in
+    /// `utf8` by default.
+    pub encoding: Option<Encoding>,
+}
+
+/// What part of the file a `fs.read` returned.
+#[derive(Debug, Serialize)]
+pub struct ReadOutput {
+    /// Total size of the file in bytes.
+    pub size: u64,
+    /// Position the returned content starts at.
+    pub offset: u64,
+    /// Number of bytes returned.
+    pub bytes: u64,
+    /// Whether the file continues after the returned content.
+    pub has_more: bool,
+    /// Offset to read the rest of the file from.
+    pub next_offset: u64,
+    /// How the returned content is encoded.
+    pub encoding: Encoding,
+    /// MIME type of the file.
+    pub mime_type: String,
+    pub modified: Option<DateTime<Utc>>,
+    /// Hex sha-256 of the whole file, when the whole file was returned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
 }
 
-pub struct FsRead;
+pub struct FsRead {
+    ctx: Arc<FsContext>,
+}
 
 impl TypedToolHandler for FsRead {
     type Params = ReadParams;
@@ -67,15 +264,67 @@ impl TypedToolHandler for FsRead {
     }
 
     async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
-        let content = tokio::fs::read(&params.path)
+        use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+        let path = self.ctx.path(&params.path).await?;
+        let mut file = tokio::fs::File::open(&path)
+            .await
+            .map_err(mcp_core::ToolError::execution)?;
+        let metadata = file
+            .metadata()
+            .await
+            .map_err(mcp_core::ToolError::execution)?;
+        let size = metadata.len();
+        let offset = params.offset.unwrap_or(0).min(size);
+        let length = params
+            .length
+            .unwrap_or(u64::MAX)
+            .min(self.ctx.max_read_bytes)
+            .min(size - offset);
+        file.seek(tokio::io::SeekFrom::Start(offset))
+            .await
+            .map_err(mcp_core::ToolError::execution)?;
+        let mut content = Vec::with_capacity(length as usize);
+        file.take(length)
+            .read_to_end(&mut content)
             .await
             .map_err(mcp_core::ToolError::execution)?;
-        let content = String::from_utf8_lossy(&content).to_string();
-        Ok(vec![Content::text(content)])
+        let binary = encoding::is_binary(&content);
+        let encoding = params.encoding.unwrap_or(if binary {
+            Encoding::Base64
+        } else {
+            Encoding::Utf8
+        });
+        // don't split a character between two reads, the rest of it comes with the next one
+        let split_char = std::str::from_utf8(&content)
+            .err()
+            .filter(|e| e.error_len().is_none() && e.valid_up_to() > 0);
+        if let Some(e) = split_char.filter(|_| encoding == Encoding::Utf8 && offset + length < size)
+        {
+            content.truncate(e.valid_up_to());
+        }
+        let bytes = content.len() as u64;
+        let output = ReadOutput {
+            size,
+            offset,
+            bytes,
+            has_more: offset + bytes < size,
+            next_offset: offset + bytes,
+            encoding,
+            mime_type: encoding::mime_type(&path, binary),
+            modified: metadata.modified().ok().map(DateTime::from),
+            sha256: (offset == 0 && bytes == size).then(|| atomic::sha256(&content)),
+        };
+        Ok(vec![
+            Content::text(encoding.encode(&content)),
+            Content::text(serde_json::to_string(&output).expect("invalid json")),
+        ])
     }
 }
 
-pub struct FsWrite;
+pub struct FsWrite {
+    ctx: Arc<FsContext>,
+}
 
 impl TypedToolHandler for FsWrite {
     type Params = WriteParams;
@@ -88,26 +337,174 @@ impl TypedToolHandler for FsWrite {
     }
 
     async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
-        use tokio::io::{AsyncSeekExt, AsyncWriteExt};
-
-        let mut file = tokio::fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&params.path)
+        let path = self.ctx.path(&params.path).await?;
+        let mode = params
+            .write_mode()
+            .map_err(mcp_core::ToolError::validation)?;
+        let data = params
+            .encoding
+            .unwrap_or(Encoding::Utf8)
+            .decode(&params.data)
+            .map_err(mcp_core::ToolError::validation)?;
+        if self.ctx.mode.guard(self.name())? {
+            let size = tokio::fs::metadata(&path).await.ok().map(|m| m.len());
+            return Ok(mode::dry_run(
+                self.name(),
+                json!({
+                    "path": params.path,
+                    "mode": mode,
+                    "offset": params.offset,
+                    "line": params.line,
+                    "bytes": data.len(),
+                    "size": size,
+                    "create_parents": params.create_parents,
+                }),
+            ));
+        }
+        let _lock = atomic::lock(&path).await;
+        let snapshot = self
+            .ctx
+            .journal
+            .snapshot(vec![Target::Entry(path.clone())])
+            .await;
+        if let Some(parent) = path.parent().filter(|_| params.create_parents) {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(mcp_core::ToolError::execution)?;
+        }
+        let (bytes_written, size, sha256) = match mode {
+            WriteMode::Append | WriteMode::AtOffset => {
+                params
+                    .precondition
+                    .check(&path, None)
+                    .await
+                    .map_err(|conflict| {
+                        ToolError::ExecutionError(format!("conflict: {conflict}"))
+                    })?;
+                let size = write_in_place(&path, mode, params.offset.unwrap_or(0), &data).await?;
+                (data.len(), size, None)
+            }
+            WriteMode::Overwrite | WriteMode::InsertAtLine => {
+                let existing = match tokio::fs::read(&path).await {
+                    Ok(existing) => Some(existing),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                    Err(e) => return Err(mcp_core::ToolError::execution(e)),
+                };
+                params
+                    .precondition
+                    .check(&path, existing.as_deref())
+                    .await
+                    .map_err(|conflict| {
+                        ToolError::ExecutionError(format!("conflict: {conflict}"))
+                    })?;
+                let mut content = existing.unwrap_or_default();
+                let bytes_written = if mode == WriteMode::InsertAtLine {
+                    let line = params.line.ok_or_else(|| {
+                        mcp_core::ToolError::validation("line is required to insert at a line")
+                    })?;
+                    let (at, inserted) = insert_at_line(&content, line, &data)
+                        .map_err(mcp_core::ToolError::validation)?;
+                    content.splice(at..at, inserted.iter().copied());
+                    inserted.len()
+                } else {
+                    content = data;
+                    content.len()
+                };
+                atomic::write(&path, &content)
+                    .await
+                    .map_err(mcp_core::ToolError::execution)?;
+                (
+                    bytes_written,
+                    content.len() as u64,
+                    Some(atomic::sha256(&content)),
+                )
+            }
+        };
+        self.ctx.journal.record(self.name(), snapshot);
+        let modified = tokio::fs::metadata(&path)
             .await
-            .map_err(mcp_core::ToolError::execution)?;
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .map(DateTime::<Utc>::from);
+        let output = json!({
+            "bytes_written": bytes_written,
+            "size": size,
+            "sha256": sha256,
+            "modified": modified,
+        });
+        Ok(vec![Content::text(
+            serde_json::to_string(&output).expect("invalid json"),
+        )])
+    }
+}
 
-        file.seek(tokio::io::SeekFrom::Start(params.offset))
+/// Append `data` to the file at `path`, or write it over the bytes at `offset`, in place
+/// rather than rewriting the whole file, and return the size of the file after the write.
+async fn write_in_place(
+    path: &Path,
+    mode: WriteMode,
+    offset: u64,
+    data: &[u8],
+) -> mcp_core::ToolResult<u64> {
+    use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+    let mut options = tokio::fs::OpenOptions::new();
+    options.create(true);
+    if mode == WriteMode::Append {
+        options.append(true);
+    } else {
+        options.write(true);
+    }
+    let mut file = options
+        .open(path)
+        .await
+        .map_err(mcp_core::ToolError::execution)?;
+    if mode == WriteMode::AtOffset {
+        let size = file
+            .metadata()
             .await
-            .map_err(mcp_core::ToolError::execution)?;
-
-        file.write_all(params.data.as_bytes())
+            .map_err(mcp_core::ToolError::execution)?
+            .len();
+        // a write past the end would leave a gap of zeros, which is never what was meant
+        if offset > size {
+            return Err(mcp_core::ToolError::validation(format!(
+                "offset {offset} is past the end of the file, which has {size} bytes"
+            )));
+        }
+        offset
+            .checked_add(data.len() as u64)
+            .ok_or_else(|| mcp_core::ToolError::validation("offset is too large"))?;
+        file.seek(tokio::io::SeekFrom::Start(offset))
             .await
             .map_err(mcp_core::ToolError::execution)?;
-
-        Ok(vec![])
     }
+    file.write_all(data)
+        .await
+        .map_err(mcp_core::ToolError::execution)?;
+    file.sync_data()
+        .await
+        .map_err(mcp_core::ToolError::execution)?;
+    let size = file
+        .metadata()
+        .await
+        .map_err(mcp_core::ToolError::execution)?
+        .len();
+    Ok(size)
+}
+
+/// Where [`FsWrite`] puts the data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WriteMode {
+    /// Replace the whole content of the file, atomically.
+    Overwrite,
+    /// Add the data at the end of the file, in place.
+    Append,
+    /// Overwrite the bytes starting at `offset`, keeping the rest of the file, in place;
+    /// `offset` can't be past the end of the file.
+    AtOffset,
+    /// Insert the data before line `line`, keeping the rest of the file, atomically.
+    InsertAtLine,
 }
 
 /// Parameters for writing to a file.
@@ -115,13 +512,85 @@ impl TypedToolHandler for FsWrite {
 pub struct WriteParams {
     /// Path to the file to be written to.
     pub path: String,
-    /// Starting position (in bytes) at which to write.
-    pub offset: u64,
     /// Data to be written to the file.
     pub data: String,
+    /// How `data` is encoded, `utf8` by default, use `base64` to write binary content.
+    pub encoding: Option<Encoding>,
+    /// How to write the data, `overwrite` by default, or `at_offset` when `offset` is
+    /// given.
+    pub mode: Option<WriteMode>,
+    /// Starting position (in bytes) at which to write, only in `at_offset` mode.
+    pub offset: Option<u64>,
+    /// Line number, starting at 1, before which to insert in `insert_at_line` mode.
+    pub line: Option<usize>,
+    /// Create the missing parent directories of the file.
+    #[serde(default)]
+    pub create_parents: bool,
+    #[serde(flatten)]
+    pub precondition: atomic::Precondition,
+}
+
+impl WriteParams {
+    /// The mode to write in, an `offset` implies `at_offset` and goes with no other mode.
+    pub fn write_mode(&self) -> Result<WriteMode, String> {
+        match (self.mode, self.offset) {
+            (Some(WriteMode::AtOffset) | None, Some(_)) => Ok(WriteMode::AtOffset),
+            (Some(_), Some(_)) => Err("offset can only be given in at_offset mode".to_string()),
+            (mode, None) => Ok(mode.unwrap_or(WriteMode::Overwrite)),
+        }
+    }
 }
 
-pub struct FsCreate;
+/// Find where to insert `data` before the 1-based `line` of `content`.
+///
+/// Returns the byte position and the bytes to insert there, a newline is added so the
+/// inserted text doesn't run into the following line, or into the last line when
+/// inserting at the end of a file without a trailing newline.
+fn insert_at_line(content: &[u8], line: usize, data: &[u8]) -> Result<(usize, Vec<u8>), String> {
+    if line == 0 {
+        return Err("line numbers start at 1".to_string());
+    }
+    let newlines = content.iter().filter(|b| **b == b'\n').count();
+    let total_lines = newlines + usize::from(!content.is_empty() && !content.ends_with(b"\n"));
+    let mut starts = std::iter::once(0).chain(
+        content
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| **b == b'\n')
+            .map(|(idx, _)| idx + 1),
+    );
+    let at = starts.nth(line - 1);
+    let mut inserted = Vec::with_capacity(data.len() + 2);
+    let at = match at {
+        Some(at) if at < content.len() => {
+            inserted.extend_from_slice(data);
+            if !data.ends_with(b"\n") {
+                inserted.push(b'\n');
+            }
+            at
+        }
+        // right after the last line
+        Some(at) => {
+            inserted.extend_from_slice(data);
+            at
+        }
+        None if line == total_lines + 1 => {
+            inserted.push(b'\n');
+            inserted.extend_from_slice(data);
+            content.len()
+        }
+        None => {
+            return Err(format!(
+                "line {line} is past the end of the file, which has {total_lines} lines"
+            ));
+        }
+    };
+    Ok((at, inserted))
+}
+
+pub struct FsCreate {
+    ctx: Arc<FsContext>,
+}
 
 impl TypedToolHandler for FsCreate {
     type Params = CreateParams;
@@ -134,7 +603,24 @@ impl TypedToolHandler for FsCreate {
     }
 
     async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
-        let file = tokio::fs::File::create(&params.path)
+        let path = self.ctx.path(&params.path).await?;
+        if self.ctx.mode.guard(self.name())? {
+            let exists = tokio::fs::try_exists(&path).await.unwrap_or(false);
+            return Ok(mode::dry_run(
+                self.name(),
+                json!({
+                    "path": params.path,
+                    "permissions": format!("{:o}", params.permissions),
+                    "truncates": exists,
+                }),
+            ));
+        }
+        let snapshot = self
+            .ctx
+            .journal
+            .snapshot(vec![Target::Entry(path.clone())])
+            .await;
+        let file = tokio::fs::File::create(&path)
             .await
             .map_err(mcp_core::ToolError::execution)?;
 
@@ -145,9 +631,10 @@ impl TypedToolHandler for FsCreate {
             .permissions();
 
         perms.set_mode(params.permissions);
-        tokio::fs::set_permissions(&params.path, perms)
+        tokio::fs::set_permissions(&path, perms)
             .await
             .map_err(mcp_core::ToolError::execution)?;
+        self.ctx.journal.record(self.name(), snapshot);
 
         Ok(vec![])
     }
@@ -162,7 +649,9 @@ pub struct CreateParams {
     pub permissions: u32,
 }
 
-pub struct FsDelete;
+pub struct FsDelete {
+    ctx: Arc<FsContext>,
+}
 
 impl TypedToolHandler for FsDelete {
     type Params = DeleteParams;
@@ -175,9 +664,46 @@ impl TypedToolHandler for FsDelete {
     }
 
     async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
-        tokio::fs::remove_file(&params.path)
+        let path = self.ctx.entry(&params.path).await?;
+        if self.ctx.mode.guard(self.name())? {
+            let metadata = tokio::fs::symlink_metadata(&path)
+                .await
+                .map_err(mcp_core::ToolError::execution)?;
+            return Ok(mode::dry_run(
+                self.name(),
+                json!({
+                    "path": params.path,
+                    "bytes": metadata.len(),
+                    "trash": self.ctx.trash.is_some(),
+                }),
+            ));
+        }
+        if let Some(trash) = self
+            .ctx
+            .trash
+            .as_ref()
+            .filter(|trash| !trash.contains(&path))
+        {
+            let metadata = tokio::fs::symlink_metadata(&path)
+                .await
+                .map_err(mcp_core::ToolError::execution)?;
+            if metadata.is_dir() {
+                return Err(mcp_core::ToolError::validation(format!(
+                    "{} is a directory, use fs.remove_directory",
+                    params.path
+                )));
+            }
+            return trash::trash(&self.ctx, trash, self.name(), &path).await;
+        }
+        let snapshot = self
+            .ctx
+            .journal
+            .snapshot(vec![Target::Entry(path.clone())])
+            .await;
+        tokio::fs::remove_file(&path)
             .await
             .map_err(mcp_core::ToolError::execution)?;
+        self.ctx.journal.record(self.name(), snapshot);
         Ok(vec![])
     }
 }
@@ -189,134 +715,10 @@ pub struct DeleteParams {
     pub path: String,
 }
 
-pub struct FsRename;
-
-impl TypedToolHandler for FsRename {
-    type Params = RenameParams;
-    fn name(&self) -> &'static str {
-        "fs.rename"
-    }
-
-    fn description(&self) -> &'static str {
-        embed!("tools/fs/rename")
-    }
-
-    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
-        tokio::fs::rename(&params.old_path, &params.new_path)
-            .await
-            .map_err(mcp_core::ToolError::execution)?;
-        Ok(vec![])
-    }
-}
-
-/// Parameters for renaming a file.
-#[derive(Debug, Deserialize, Serialize, JsonSchema)]
-pub struct RenameParams {
-    /// Current path of the file.
-    pub old_path: String,
-    /// New path for the file.
-    pub new_path: String,
-}
-
-pub struct FsMove;
-
-impl TypedToolHandler for FsMove {
-    type Params = MoveParams;
-    fn name(&self) -> &'static str {
-        "fs.move"
-    }
-
-    fn description(&self) -> &'static str {
-        embed!("tools/fs/move")
-    }
-
-    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
-        tokio::fs::rename(&params.source_path, &params.destination_path)
-            .await
-            .map_err(mcp_core::ToolError::execution)?;
-        Ok(vec![])
-    }
-}
-
-/// Parameters for moving a file.
-#[derive(Debug, Deserialize, Serialize, JsonSchema)]
-pub struct MoveParams {
-    /// Current path of the file to be moved.
-    pub source_path: String,
-    /// Destination path where the file should be moved to.
-    pub destination_path: String,
+pub struct FsMakeDirectory {
+    ctx: Arc<FsContext>,
 }
 
-pub struct FsCopy;
-
-impl TypedToolHandler for FsCopy {
-    type Params = CopyParams;
-    fn name(&self) -> &'static str {
-        "fs.copy"
-    }
-
-    fn description(&self) -> &'static str {
-        embed!("tools/fs/copy")
-    }
-
-    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
-        let size = tokio::fs::copy(&params.source_path, &params.destination_path)
-            .await
-            .map_err(mcp_core::ToolError::execution)?;
-        Ok(vec![Content::text(size.to_string())])
-    }
-}
-
-/// Parameters for copying a file.
-#[derive(Debug, Deserialize, Serialize, JsonSchema)]
-pub struct CopyParams {
-    /// Path of the file to be copied.
-    pub source_path: String,
-    /// Destination path where the file should be copied to.
-    pub destination_path: String,
-}
-
-pub struct FsListDirectory;
-
-impl TypedToolHandler for FsListDirectory {
-    type Params = ListDirectoryParams;
-    fn name(&self) -> &'static str {
-        "fs.list_directory"
-    }
-
-    fn description(&self) -> &'static str {
-        embed!("tools/fs/list")
-    }
-
-    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
-        let mut entries = tokio::fs::read_dir(&params.path)
-            .await
-            .map_err(mcp_core::ToolError::execution)?;
-
-        let mut contents = String::new();
-        while let Some(entry) = entries
-            .next_entry()
-            .await
-            .map_err(mcp_core::ToolError::execution)?
-        {
-            use std::fmt::Write;
-            let path = entry.path();
-            writeln!(&mut contents, "{}", path.to_string_lossy()).map_err(ToolError::execution)?;
-        }
-
-        Ok(vec![Content::text(contents)])
-    }
-}
-
-/// Parameters for listing the contents of a directory.
-#[derive(Debug, Deserialize, Serialize, JsonSchema)]
-pub struct ListDirectoryParams {
-    /// Path to the directory to be listed.
-    pub path: String,
-}
-
-pub struct FsMakeDirectory;
-
 impl TypedToolHandler for FsMakeDirectory {
     type Params = MakeDirectoryParams;
     fn name(&self) -> &'static str {
@@ -328,14 +730,30 @@ impl TypedToolHandler for FsMakeDirectory {
     }
 
     async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
-        tokio::fs::create_dir(&params.path)
+        let path = self.ctx.entry(&params.path).await?;
+        if self.ctx.mode.guard(self.name())? {
+            return Ok(mode::dry_run(
+                self.name(),
+                json!({
+                    "path": params.path,
+                    "permissions": format!("{:o}", params.permissions),
+                }),
+            ));
+        }
+        let snapshot = self
+            .ctx
+            .journal
+            .snapshot(vec![Target::Entry(path.clone())])
+            .await;
+        tokio::fs::create_dir(&path)
             .await
             .map_err(mcp_core::ToolError::execution)?;
 
         let perms = std::fs::Permissions::from_mode(params.permissions);
-        tokio::fs::set_permissions(&params.path, perms)
+        tokio::fs::set_permissions(&path, perms)
             .await
             .map_err(mcp_core::ToolError::execution)?;
+        self.ctx.journal.record(self.name(), snapshot);
 
         Ok(vec![])
     }
@@ -350,7 +768,9 @@ pub struct MakeDirectoryParams {
     pub permissions: u32,
 }
 
-pub struct FsRemoveDirectory;
+pub struct FsRemoveDirectory {
+    ctx: Arc<FsContext>,
+}
 
 impl TypedToolHandler for FsRemoveDirectory {
     type Params = RemoveDirectoryParams;
@@ -363,15 +783,58 @@ impl TypedToolHandler for FsRemoveDirectory {
     }
 
     async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        let path = self.ctx.entry(&params.path).await?;
+        if self.ctx.mode.guard(self.name())? {
+            let usage_path = path.clone();
+            let (entries, bytes) = tokio::task::spawn_blocking(move || disk_usage(&usage_path))
+                .await
+                .map_err(mcp_core::ToolError::execution)?
+                .map_err(mcp_core::ToolError::execution)?;
+            return Ok(mode::dry_run(
+                self.name(),
+                json!({
+                    "path": params.path,
+                    "recursive": params.recursive,
+                    "entries": entries,
+                    "bytes": bytes,
+                    "trash": params.recursive && self.ctx.trash.is_some(),
+                }),
+            ));
+        }
+        // a directory removed on its own is empty, there's nothing to keep of it
+        let trashed = self
+            .ctx
+            .trash
+            .as_ref()
+            .filter(|trash| params.recursive && !trash.contains(&path));
+        if let Some(trash) = trashed {
+            let metadata = tokio::fs::symlink_metadata(&path)
+                .await
+                .map_err(mcp_core::ToolError::execution)?;
+            if !metadata.is_dir() {
+                return Err(mcp_core::ToolError::validation(format!(
+                    "{} is not a directory",
+                    params.path
+                )));
+            }
+            return trash::trash(&self.ctx, trash, self.name(), &path).await;
+        }
+        let target = if params.recursive {
+            Target::Tree(path.clone())
+        } else {
+            Target::Entry(path.clone())
+        };
+        let snapshot = self.ctx.journal.snapshot(vec![target]).await;
         if params.recursive {
-            tokio::fs::remove_dir_all(&params.path)
+            tokio::fs::remove_dir_all(&path)
                 .await
                 .map_err(mcp_core::ToolError::execution)?;
         } else {
-            tokio::fs::remove_dir(&params.path)
+            tokio::fs::remove_dir(&path)
                 .await
                 .map_err(mcp_core::ToolError::execution)?;
         }
+        self.ctx.journal.record(self.name(), snapshot);
         Ok(vec![])
     }
 }
@@ -385,54 +848,10 @@ pub struct RemoveDirectoryParams {
     pub recursive: bool,
 }
 
-pub struct FsGetFileInfo;
-
-impl TypedToolHandler for FsGetFileInfo {
-    type Params = GetFileInfoParams;
-    fn name(&self) -> &'static str {
-        "fs.get_file_info"
-    }
-
-    fn description(&self) -> &'static str {
-        embed!("tools/fs/info")
-    }
-
-    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
-        let metadata = tokio::fs::metadata(&params.path)
-            .await
-            .map_err(mcp_core::ToolError::execution)?;
-        let modified = metadata
-            .modified()
-            .map_err(ToolError::execution)
-            .map(|t| t.duration_since(UNIX_EPOCH).expect("time goes back"))?;
-        let modified = chrono::DateTime::<Utc>::from_timestamp(
-            modified.as_secs() as i64,
-            modified.subsec_nanos(),
-        );
-        let info = serde_json::json!({
-            "path": params.path,
-            "size": metadata.len(),
-            "modified": modified,
-            "is_dir": metadata.is_dir(),
-            "is_file": metadata.is_file(),
-            "permissions": format!("{:o}", metadata.permissions().mode()),
-        });
-
-        Ok(vec![Content::text(
-            serde_json::to_string(&info).expect("invalid json"),
-        )])
-    }
-}
-
-/// Parameters for retrieving file information.
-#[derive(Debug, Deserialize, Serialize, JsonSchema)]
-pub struct GetFileInfoParams {
-    /// Path to the file for which to get information.
-    pub path: String,
+pub struct FsSetPermissions {
+    ctx: Arc<FsContext>,
 }
 
-pub struct FsSetPermissions;
-
 impl TypedToolHandler for FsSetPermissions {
     type Params = SetFilePermissionsParams;
     fn name(&self) -> &'static str {
@@ -444,10 +863,30 @@ impl TypedToolHandler for FsSetPermissions {
     }
 
     async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        let path = self.ctx.path(&params.path).await?;
+        if self.ctx.mode.guard(self.name())? {
+            let metadata = tokio::fs::metadata(&path)
+                .await
+                .map_err(mcp_core::ToolError::execution)?;
+            return Ok(mode::dry_run(
+                self.name(),
+                json!({
+                    "path": params.path,
+                    "from": format!("{:o}", metadata.permissions().mode()),
+                    "to": format!("{:o}", params.permissions),
+                }),
+            ));
+        }
+        let snapshot = self
+            .ctx
+            .journal
+            .snapshot(vec![Target::Metadata(path.clone())])
+            .await;
         let perms = std::fs::Permissions::from_mode(params.permissions);
-        tokio::fs::set_permissions(&params.path, perms)
+        tokio::fs::set_permissions(&path, perms)
             .await
             .map_err(mcp_core::ToolError::execution)?;
+        self.ctx.journal.record(self.name(), snapshot);
         Ok(vec![])
     }
 }
@@ -461,7 +900,9 @@ pub struct SetFilePermissionsParams {
     pub permissions: u32,
 }
 
-pub struct FsChangeOwnership;
+pub struct FsChangeOwnership {
+    ctx: Arc<FsContext>,
+}
 
 impl TypedToolHandler for FsChangeOwnership {
     type Params = ChangeOwnershipParams;
@@ -474,11 +915,31 @@ impl TypedToolHandler for FsChangeOwnership {
     }
 
     async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        let path = self.ctx.path(&params.path).await?;
+        if self.ctx.mode.guard(self.name())? {
+            let metadata = tokio::fs::metadata(&path)
+                .await
+                .map_err(mcp_core::ToolError::execution)?;
+            return Ok(mode::dry_run(
+                self.name(),
+                json!({
+                    "path": params.path,
+                    "from": { "user_id": metadata.uid(), "group_id": metadata.gid() },
+                    "to": { "user_id": params.user_id, "group_id": params.group_id },
+                }),
+            ));
+        }
         #[cfg(unix)]
         {
             use std::os::unix::fs::chown;
-            chown(&params.path, Some(params.user_id), Some(params.group_id))
+            let snapshot = self
+                .ctx
+                .journal
+                .snapshot(vec![Target::Metadata(path.clone())])
+                .await;
+            chown(&path, Some(params.user_id), Some(params.group_id))
                 .map_err(mcp_core::ToolError::execution)?;
+            self.ctx.journal.record(self.name(), snapshot);
             Ok(vec![])
         }
 
@@ -499,3 +960,172 @@ pub struct ChangeOwnershipParams {
     /// Group ID to set as the file's group.
     pub group_id: u32,
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::testing::{Scratch, call, call_json, context};
+    use super::*;
+
+    async fn read(max_read_bytes: u64, params: Value) -> (String, Value) {
+        let tool = FsRead {
+            ctx: context(Fs {
+                max_read_bytes,
+                ..Fs::default()
+            }),
+        };
+        let mut texts = call(&tool, params).await.unwrap();
+        let output = serde_json::from_str(&texts.pop().unwrap()).unwrap();
+        (texts.pop().unwrap(), output)
+    }
+
+    async fn write(params: Value) -> mcp_core::ToolResult<Value> {
+        let tool = FsWrite {
+            ctx: context(Fs::default()),
+        };
+        call_json(&tool, params).await
+    }
+
+    fn insert(content: &str, line: usize, data: &str) -> Result<String, String> {
+        let (at, inserted) = insert_at_line(content.as_bytes(), line, data.as_bytes())?;
+        let mut content = content.as_bytes().to_vec();
+        content.splice(at..at, inserted);
+        Ok(String::from_utf8(content).unwrap())
+    }
+
+    #[test]
+    fn inserts_before_a_line() {
+        assert_eq!(insert("a\nb\n", 1, "x").unwrap(), "x\na\nb\n");
+        assert_eq!(insert("a\nb\n", 2, "x").unwrap(), "a\nx\nb\n");
+        // data which ends a line already doesn't get another newline
+        assert_eq!(insert("a\nb\n", 2, "x\n").unwrap(), "a\nx\nb\n");
+        assert_eq!(insert("a\nb\n", 1, "x\ny").unwrap(), "x\ny\na\nb\n");
+    }
+
+    #[test]
+    fn inserts_after_the_last_line() {
+        assert_eq!(insert("a\nb\n", 3, "x\n").unwrap(), "a\nb\nx\n");
+        // the last line is ended first when the file doesn't end with a newline
+        assert_eq!(insert("a\nb", 3, "x").unwrap(), "a\nb\nx");
+        assert_eq!(insert("", 1, "x\n").unwrap(), "x\n");
+    }
+
+    #[test]
+    fn rejects_lines_out_of_the_file() {
+        assert_eq!(
+            insert("a\n", 0, "x").unwrap_err(),
+            "line numbers start at 1"
+        );
+        assert_eq!(
+            insert("a\nb\n", 4, "x").unwrap_err(),
+            "line 4 is past the end of the file, which has 2 lines"
+        );
+        assert!(insert("a\nb", 4, "x").is_err());
+        assert!(insert("", 2, "x").is_err());
+    }
+
+    #[tokio::test]
+    async fn appends_to_a_file() {
+        let dir = Scratch::new("append");
+        dir.write("file", "a\n");
+        let result = write(json!({"path": dir.param("file"), "data": "b\n", "mode": "append"}))
+            .await
+            .unwrap();
+        assert_eq!(dir.read("file"), "a\nb\n");
+        assert_eq!(result["bytes_written"], 2);
+        assert_eq!(result["size"], 4);
+        assert!(result["sha256"].is_null());
+    }
+
+    #[tokio::test]
+    async fn writes_at_an_offset() {
+        let dir = Scratch::new("at-offset");
+        dir.write("file", "abcdef");
+        write(json!({"path": dir.param("file"), "data": "XY", "offset": 2}))
+            .await
+            .unwrap();
+        assert_eq!(dir.read("file"), "abXYef");
+        // up to the end of the file, which grows
+        write(json!({"path": dir.param("file"), "data": "Z", "mode": "at_offset", "offset": 6}))
+            .await
+            .unwrap();
+        assert_eq!(dir.read("file"), "abXYefZ");
+    }
+
+    #[tokio::test]
+    async fn rejects_an_offset_past_the_end() {
+        let dir = Scratch::new("past-end");
+        dir.write("file", "abc");
+        let error = write(json!({"path": dir.param("file"), "data": "x", "offset": 4}))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("past the end"), "{error}");
+        assert_eq!(dir.read("file"), "abc");
+    }
+
+    #[tokio::test]
+    async fn rejects_an_offset_in_other_modes() {
+        let dir = Scratch::new("offset-mode");
+        dir.write("file", "abc");
+        for mode in ["overwrite", "append", "insert_at_line"] {
+            let error = write(json!({
+                "path": dir.param("file"),
+                "data": "x",
+                "mode": mode,
+                "offset": 1,
+                "line": 1,
+            }))
+            .await
+            .unwrap_err();
+            assert!(matches!(error, ToolError::InvalidParameters(_)), "{error}");
+        }
+        assert_eq!(dir.read("file"), "abc");
+    }
+
+    #[tokio::test]
+    async fn creates_parents_only_when_asked() {
+        let dir = Scratch::new("parents");
+        let path = dir.param("a/b/file");
+        assert!(write(json!({"path": path, "data": "x"})).await.is_err());
+        write(json!({"path": path, "data": "x", "create_parents": true}))
+            .await
+            .unwrap();
+        assert_eq!(dir.read("a/b/file"), "x");
+    }
+
+    #[tokio::test]
+    async fn reads_up_to_the_maximum_read_size() {
+        let dir = Scratch::new("read-max");
+        dir.write("file", "abcdefgh");
+        let (content, output) = read(4, json!({"path": dir.param("file")})).await;
+        assert_eq!(content, "abcd");
+        assert_eq!(output["has_more"], true);
+        assert_eq!(output["next_offset"], 4);
+        assert!(output.get("sha256").is_none());
+        let (content, output) = read(4, json!({"path": dir.param("file"), "offset": 6})).await;
+        assert_eq!(content, "gh");
+        assert_eq!(output["has_more"], false);
+        let (_, output) = read(8, json!({"path": dir.param("file")})).await;
+        assert_eq!(output["sha256"], atomic::sha256(b"abcdefgh"));
+    }
+
+    #[tokio::test]
+    async fn doesnt_split_a_character_between_reads() {
+        let dir = Scratch::new("read-utf8");
+        dir.write("file", "aé€");
+        let (content, output) = read(100, json!({"path": dir.param("file"), "length": 2})).await;
+        assert_eq!(content, "a");
+        assert_eq!(output["next_offset"], 1);
+        let (content, output) = read(4, json!({"path": dir.param("file"), "offset": 1})).await;
+        assert_eq!(content, "é");
+        assert_eq!(output["next_offset"], 3);
+        let (content, output) = read(100, json!({"path": dir.param("file"), "offset": 3})).await;
+        assert_eq!(content, "€");
+        assert_eq!(output["has_more"], false);
+        // a character cut by the end of the file is returned as it is
+        dir.write("cut", &"é".as_bytes()[..1]);
+        let (content, _) = read(100, json!({"path": dir.param("cut"), "encoding": "base64"})).await;
+        assert_eq!(content, "ww==");
+    }
+}
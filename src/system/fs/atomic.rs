@@ -0,0 +1,249 @@
+//! Replacing file content so that readers see either the old or the new content.
+
+use std::{
+    collections::HashMap,
+    io,
+    os::unix::fs::{MetadataExt, fchown},
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Mutex, Weak},
+};
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+
+/// Write `content` to a temporary file next to `path`, sync it and rename it over `path`.
+///
+/// The permissions and, as far as the server may, the owner of an existing file are kept.
+/// `path` must be resolved already, a symlink is refused rather than replaced by a file.
+pub async fn write(path: &Path, content: &[u8]) -> io::Result<()> {
+    if tokio::fs::symlink_metadata(path)
+        .await
+        .is_ok_and(|metadata| metadata.file_type().is_symlink())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is a symbolic link", path.display()),
+        ));
+    }
+    let temp = temp_path(path);
+    let result = async {
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)
+            .await?;
+        if let Ok(metadata) = tokio::fs::metadata(path).await {
+            // only root may give files away, others keep the file as their own
+            match fchown(&file, Some(metadata.uid()), Some(metadata.gid())) {
+                Err(e) if e.kind() != io::ErrorKind::PermissionDenied => return Err(e),
+                _ => {}
+            }
+            // after chown, which clears the setuid and setgid bits
+            file.set_permissions(metadata.permissions()).await?;
+        }
+        file.write_all(content).await?;
+        file.sync_all().await?;
+        drop(file);
+        tokio::fs::rename(&temp, path).await
+    }
+    .await;
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&temp).await;
+        return result;
+    }
+    // make the rename itself durable, the content is replaced either way
+    let parent = path.parent().unwrap_or(Path::new("/"));
+    if let Ok(parent) = tokio::fs::File::open(parent).await {
+        let _ = parent.sync_all().await;
+    }
+    Ok(())
+}
+
+/// A hidden sibling of `path` which no one else uses.
+pub fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(
+        ".{name}.llmidium-{:08x}.tmp",
+        rand::random::<u32>()
+    ))
+}
+
+/// Hex sha-256 of `content`, as `sha256sum` prints it.
+pub fn sha256(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// Hex sha-256 of the file at `path`, read in chunks rather than all at once.
+pub async fn sha256_file(path: &Path) -> io::Result<String> {
+    use tokio::io::AsyncReadExt;
+
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut buffer).await? {
+            0 => return Ok(format!("{:x}", hasher.finalize())),
+            read => hasher.update(&buffer[..read]),
+        }
+    }
+}
+
+/// Serialize the writes of a file across the sessions of the server, so that what a write
+/// checked before replacing the file still holds when it does.
+pub async fn lock(path: &Path) -> tokio::sync::OwnedMutexGuard<()> {
+    type Locks = Mutex<HashMap<PathBuf, Weak<tokio::sync::Mutex<()>>>>;
+    static LOCKS: LazyLock<Locks> = LazyLock::new(Default::default);
+    let lock = {
+        let mut locks = LOCKS.lock().expect("poisoned");
+        locks.retain(|_, lock| lock.strong_count() > 0);
+        match locks.get(path).and_then(Weak::upgrade) {
+            Some(lock) => lock,
+            None => {
+                let lock = Arc::new(tokio::sync::Mutex::new(()));
+                locks.insert(path.to_path_buf(), Arc::downgrade(&lock));
+                lock
+            }
+        }
+    };
+    lock.lock_owned().await
+}
+
+/// What a file must still be for a write to replace it, so that the write doesn't clobber
+/// changes made since the file was read.
+#[derive(Debug, Default, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct Precondition {
+    /// Hex sha-256 the content of the file must have, as `fs.read` reports it, the write
+    /// fails with a conflict otherwise.
+    pub expected_sha256: Option<String>,
+    /// Modification time the file must have, as `fs.read` or `fs.get_file_info` report it,
+    /// the write fails with a conflict otherwise.
+    pub expected_mtime: Option<DateTime<Utc>>,
+}
+
+impl Precondition {
+    /// Check the file at `path`, whose `content` is given when it was read already.
+    ///
+    /// Fails with a description of the conflict when the file isn't what is expected.
+    pub async fn check(&self, path: &Path, content: Option<&[u8]>) -> Result<(), String> {
+        if self.expected_sha256.is_none() && self.expected_mtime.is_none() {
+            return Ok(());
+        }
+        let metadata = match tokio::fs::metadata(path).await {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(format!("{} doesn't exist", path.display()));
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+        if let Some(expected) = self.expected_mtime {
+            let modified = metadata.modified().map_err(|e| e.to_string())?;
+            let modified = DateTime::<Utc>::from(modified);
+            if modified != expected {
+                return Err(format!(
+                    "{} was modified at {}, not at {}",
+                    path.display(),
+                    modified.to_rfc3339(),
+                    expected.to_rfc3339()
+                ));
+            }
+        }
+        if let Some(expected) = &self.expected_sha256 {
+            let actual = match content {
+                Some(content) => sha256(content),
+                None => sha256_file(path).await.map_err(|e| e.to_string())?,
+            };
+            if !actual.eq_ignore_ascii_case(expected) {
+                return Err(format!(
+                    "the sha256 of {} is {actual}, not {expected}",
+                    path.display()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::{PermissionsExt, symlink};
+
+    use super::super::testing::Scratch;
+    use super::*;
+
+    #[tokio::test]
+    async fn detects_precondition_conflicts() {
+        let dir = Scratch::new("atomic-precondition");
+        let path = dir.write("file", "old");
+        let mtime = DateTime::<Utc>::from(std::fs::metadata(&path).unwrap().modified().unwrap());
+        let matching = Precondition {
+            expected_sha256: Some(sha256(b"old").to_uppercase()),
+            expected_mtime: Some(mtime),
+        };
+        matching.check(&path, None).await.unwrap();
+        matching.check(&path, Some(b"old")).await.unwrap();
+
+        let changed = Precondition {
+            expected_sha256: Some(sha256(b"new")),
+            ..Precondition::default()
+        };
+        let conflict = changed.check(&path, None).await.unwrap_err();
+        assert!(conflict.contains(&sha256(b"old")), "{conflict}");
+        let touched = Precondition {
+            expected_mtime: Some(mtime - chrono::Duration::seconds(1)),
+            ..Precondition::default()
+        };
+        let conflict = touched.check(&path, None).await.unwrap_err();
+        assert!(conflict.contains("was modified at"), "{conflict}");
+        let conflict = matching
+            .check(&dir.join("missing"), None)
+            .await
+            .unwrap_err();
+        assert!(conflict.ends_with("doesn't exist"), "{conflict}");
+
+        // nothing expected, nothing to check
+        Precondition::default()
+            .check(&dir.join("missing"), None)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_to_write_through_symlinks() {
+        let dir = Scratch::new("atomic-symlink");
+        dir.write("target", "old");
+        symlink("target", dir.join("link")).unwrap();
+        let error = write(&dir.join("link"), b"new").await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(dir.read("target"), "old");
+        assert!(
+            std::fs::symlink_metadata(dir.join("link"))
+                .unwrap()
+                .file_type()
+                .is_symlink()
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_permissions_and_leaves_no_temporary_files() {
+        let dir = Scratch::new("atomic-write");
+        let path = dir.write("file", "old");
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+        write(&path, b"new").await.unwrap();
+        assert_eq!(dir.read("file"), "new");
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        write(&dir.join("created"), b"").await.unwrap();
+        let mut names: Vec<_> = std::fs::read_dir(&*dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["created", "file"]);
+    }
+}
@@ -0,0 +1,499 @@
+//! Copying files and directory trees, as `cp -a` would.
+
+use std::{
+    fs::{self, FileTimes, Metadata},
+    io,
+    os::unix::fs::{MetadataExt, lchown},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use mcp_core::{Content, handler::TypedToolHandler};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{FsContext, jail::PathJail, journal::Target};
+use crate::{embed, system::mode};
+
+/// Parameters for copying a file or a directory tree.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct CopyParams {
+    /// Path of the file or directory to be copied.
+    pub source_path: String,
+    /// Path of the copy, an existing directory is merged with the copied one.
+    pub destination_path: String,
+    /// Copy directories with everything in them.
+    #[serde(default)]
+    pub recursive: bool,
+    /// What to do with files which already exist at the destination, `overwrite` by default.
+    #[serde(default)]
+    pub existing: ExistingMode,
+    /// Whether symlinks are copied as links or the files they lead to are copied, `copy`
+    /// by default.
+    #[serde(default)]
+    pub symlinks: SymlinkMode,
+    /// Keep the permissions of the copied files, true by default.
+    pub preserve_mode: Option<bool>,
+    /// Keep the owner and group of the copied files, which usually needs root.
+    #[serde(default)]
+    pub preserve_ownership: bool,
+    /// Keep the access and modification times of the copied files.
+    #[serde(default)]
+    pub preserve_timestamps: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExistingMode {
+    /// Fail before copying anything.
+    Error,
+    /// Leave the existing file alone.
+    Skip,
+    #[default]
+    Overwrite,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SymlinkMode {
+    /// Copy the link itself.
+    #[default]
+    Copy,
+    /// Copy what the link leads to.
+    Follow,
+}
+
+#[derive(Debug, Clone)]
+pub struct CopyOptions {
+    pub existing: ExistingMode,
+    pub symlinks: SymlinkMode,
+    pub preserve_mode: bool,
+    pub preserve_ownership: bool,
+    pub preserve_timestamps: bool,
+}
+
+impl CopyOptions {
+    /// Copy everything as it is, as `cp -a`.
+    pub fn archive(existing: ExistingMode) -> Self {
+        CopyOptions {
+            existing,
+            symlinks: SymlinkMode::Copy,
+            preserve_mode: true,
+            preserve_ownership: true,
+            preserve_timestamps: true,
+        }
+    }
+}
+
+/// What a copy did, or would do.
+#[derive(Debug, Default, Serialize)]
+pub struct CopyReport {
+    pub files: u64,
+    pub directories: u64,
+    pub symlinks: u64,
+    /// Existing files left alone, special files and entries denied by the jail.
+    pub skipped: u64,
+    pub overwritten: u64,
+    /// Total size of the copied files.
+    pub bytes: u64,
+}
+
+/// Copies a tree entry by entry, blocking on the file system.
+pub struct Copier {
+    jail: PathJail,
+    options: CopyOptions,
+    dry_run: bool,
+    pub report: CopyReport,
+    /// Device and inode of the directories being copied, to stop at symlink loops.
+    ancestors: Vec<(u64, u64)>,
+    /// Entries created at the destination, in the order they were created.
+    created: Vec<PathBuf>,
+}
+
+impl Copier {
+    pub fn new(jail: PathJail, options: CopyOptions, dry_run: bool) -> Self {
+        Copier {
+            jail,
+            options,
+            dry_run,
+            report: CopyReport::default(),
+            ancestors: Vec::new(),
+            created: Vec::new(),
+        }
+    }
+
+    /// Remove the entries this copy created, after it failed, returning those which can't
+    /// be removed. Overwritten files are lost already.
+    pub fn remove_created(&mut self) -> Vec<PathBuf> {
+        let mut left = Vec::new();
+        // the entries of a directory were created after it
+        for path in self.created.drain(..).rev() {
+            let removed = match fs::symlink_metadata(&path) {
+                Ok(metadata) if metadata.is_dir() => fs::remove_dir(&path),
+                Ok(_) => fs::remove_file(&path),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                Err(e) => Err(e),
+            };
+            if removed.is_err() {
+                left.push(path);
+            }
+        }
+        left
+    }
+
+    /// Copy `source` to `destination`, both already resolved within the jail.
+    pub fn copy(&mut self, source: &Path, destination: &Path) -> io::Result<()> {
+        if destination.starts_with(source) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("can't copy {} to itself or into itself", source.display()),
+            ));
+        }
+        self.copy_entry(source, destination)
+    }
+
+    fn copy_entry(&mut self, source: &Path, destination: &Path) -> io::Result<()> {
+        if self.jail.is_denied(source) || self.jail.is_denied(destination) {
+            self.report.skipped += 1;
+            return Ok(());
+        }
+        let metadata = match self.options.symlinks {
+            SymlinkMode::Copy => fs::symlink_metadata(source)?,
+            SymlinkMode::Follow => {
+                self.jail.check(source, true).map_err(io::Error::other)?;
+                fs::metadata(source)?
+            }
+        };
+        let file_type = metadata.file_type();
+        // fifos, sockets and devices
+        if !file_type.is_dir() && !file_type.is_symlink() && !file_type.is_file() {
+            self.report.skipped += 1;
+            return Ok(());
+        }
+        let existing = fs::symlink_metadata(destination).ok();
+        if let Some(existing) = &existing {
+            if existing.is_dir() != metadata.is_dir() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!(
+                        "{} already exists and can't be replaced by {}",
+                        destination.display(),
+                        source.display()
+                    ),
+                ));
+            }
+            if !metadata.is_dir() {
+                match self.options.existing {
+                    ExistingMode::Error => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            format!("{} already exists", destination.display()),
+                        ));
+                    }
+                    ExistingMode::Skip => {
+                        self.report.skipped += 1;
+                        return Ok(());
+                    }
+                    ExistingMode::Overwrite => {
+                        self.report.overwritten += 1;
+                        // never write through a symlink at the destination
+                        if !self.dry_run {
+                            fs::remove_file(destination)?;
+                        }
+                    }
+                }
+            }
+        }
+
+        if file_type.is_dir() {
+            let key = (metadata.dev(), metadata.ino());
+            if self.ancestors.contains(&key) {
+                return Err(io::Error::other(format!(
+                    "{} leads back to a directory being copied",
+                    source.display()
+                )));
+            }
+            if existing.is_none() && !self.dry_run {
+                fs::create_dir(destination)?;
+                self.created.push(destination.to_path_buf());
+            }
+            self.report.directories += 1;
+            self.ancestors.push(key);
+            let mut entries = fs::read_dir(source)?.collect::<io::Result<Vec<_>>>()?;
+            entries.sort_by_key(|entry| entry.file_name());
+            for entry in entries {
+                self.copy_entry(&entry.path(), &destination.join(entry.file_name()))?;
+            }
+            self.ancestors.pop();
+        } else if file_type.is_symlink() {
+            if !self.dry_run {
+                std::os::unix::fs::symlink(fs::read_link(source)?, destination)?;
+                self.created
+                    .extend(existing.is_none().then(|| destination.to_path_buf()));
+            }
+            self.report.symlinks += 1;
+        } else {
+            if !self.dry_run {
+                if existing.is_none() {
+                    // a partly copied file is removed as well
+                    self.created.push(destination.to_path_buf());
+                }
+                self.copy_file(source, destination)?;
+            }
+            self.report.files += 1;
+            self.report.bytes += metadata.len();
+        }
+        if !self.dry_run {
+            self.preserve(&metadata, destination)?;
+        }
+        Ok(())
+    }
+
+    fn copy_file(&self, source: &Path, destination: &Path) -> io::Result<()> {
+        if self.options.preserve_mode {
+            fs::copy(source, destination)?;
+        } else {
+            let mut reader = fs::File::open(source)?;
+            let mut writer = fs::File::create_new(destination)?;
+            io::copy(&mut reader, &mut writer)?;
+        }
+        Ok(())
+    }
+
+    /// Apply the attributes of the source, after the content of a directory is copied so
+    /// that neither its permissions nor its times get in the way.
+    fn preserve(&self, metadata: &Metadata, destination: &Path) -> io::Result<()> {
+        if self.options.preserve_ownership {
+            lchown(destination, Some(metadata.uid()), Some(metadata.gid()))?;
+        }
+        // symlinks have no permissions, and std can't set their times
+        if metadata.is_symlink() {
+            return Ok(());
+        }
+        if self.options.preserve_timestamps {
+            let times = FileTimes::new()
+                .set_accessed(metadata.accessed()?)
+                .set_modified(metadata.modified()?);
+            fs::File::open(destination)?.set_times(times)?;
+        }
+        // after chown, which clears the setuid and setgid bits
+        if self.options.preserve_mode {
+            fs::set_permissions(destination, metadata.permissions())?;
+        }
+        Ok(())
+    }
+}
+
+pub struct FsCopy {
+    pub(super) ctx: Arc<FsContext>,
+}
+
+impl TypedToolHandler for FsCopy {
+    type Params = CopyParams;
+    fn name(&self) -> &'static str {
+        "fs.copy"
+    }
+
+    fn description(&self) -> &'static str {
+        embed!("tools/fs/copy")
+    }
+
+    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        let source_path = match params.symlinks {
+            SymlinkMode::Copy => self.ctx.entry(&params.source_path).await?,
+            SymlinkMode::Follow => self.ctx.path(&params.source_path).await?,
+        };
+        let destination_path = self.ctx.entry(&params.destination_path).await?;
+        let metadata = tokio::fs::symlink_metadata(&source_path)
+            .await
+            .map_err(mcp_core::ToolError::execution)?;
+        if metadata.is_dir() && !params.recursive {
+            return Err(mcp_core::ToolError::validation(format!(
+                "{} is a directory, set recursive to copy it",
+                params.source_path
+            )));
+        }
+        let options = CopyOptions {
+            existing: params.existing,
+            symlinks: params.symlinks,
+            preserve_mode: params.preserve_mode.unwrap_or(true),
+            preserve_ownership: params.preserve_ownership,
+            preserve_timestamps: params.preserve_timestamps,
+        };
+        let dry_run = self.ctx.mode.guard(self.name())?;
+        let snapshot = if dry_run {
+            None
+        } else {
+            let target = Target::Tree(destination_path.clone());
+            Some(self.ctx.journal.snapshot(vec![target]).await)
+        };
+        let jail = self.ctx.jail.clone();
+        let report = tokio::task::spawn_blocking(move || {
+            // find every conflict, and symlink loop, before copying anything
+            let mut copier = Copier::new(jail.clone(), options.clone(), true);
+            copier.copy(&source_path, &destination_path)?;
+            if dry_run {
+                return Ok(copier.report);
+            }
+            let mut copier = Copier::new(jail, options, false);
+            if let Err(e) = copier.copy(&source_path, &destination_path) {
+                let left = copier.remove_created();
+                let mut message = format!("{e}, the copied entries were removed");
+                if !left.is_empty() {
+                    let left: Vec<_> = left.iter().map(|path| path.display().to_string()).collect();
+                    message = format!("{e}, these copied entries are left: {}", left.join(", "));
+                }
+                if copier.report.overwritten > 0 {
+                    message += &format!(", {} files were overwritten", copier.report.overwritten);
+                }
+                return Err(io::Error::new(e.kind(), message));
+            }
+            Ok::<_, io::Error>(copier.report)
+        })
+        .await
+        .map_err(mcp_core::ToolError::execution)?
+        .map_err(mcp_core::ToolError::execution)?;
+        if dry_run {
+            return Ok(mode::dry_run(
+                self.name(),
+                json!({
+                    "from": params.source_path,
+                    "to": params.destination_path,
+                    "report": report,
+                }),
+            ));
+        }
+        if let Some(snapshot) = snapshot {
+            self.ctx.journal.record(self.name(), snapshot);
+        }
+        Ok(vec![Content::text(
+            serde_json::to_string(&report).expect("invalid json"),
+        )])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::{PermissionsExt, symlink};
+
+    use serde_json::{Value, json};
+
+    use super::super::{
+        Fs,
+        testing::{Scratch, call_json, context},
+    };
+    use super::*;
+
+    async fn copy(params: Value) -> mcp_core::ToolResult<Value> {
+        let tool = FsCopy {
+            ctx: context(Fs::default()),
+        };
+        call_json(&tool, params).await
+    }
+
+    #[tokio::test]
+    async fn copies_a_tree_recursively() {
+        let dir = Scratch::new("copy-tree");
+        let script = dir.write("src/bin/script", "#!/bin/sh\n");
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o750)).unwrap();
+        dir.write("src/data", "data");
+        symlink("data", dir.join("src/link")).unwrap();
+        let params = json!({"source_path": dir.param("src"), "destination_path": dir.param("dst")});
+
+        let error = copy(params.clone()).await.unwrap_err();
+        assert!(
+            matches!(error, mcp_core::ToolError::InvalidParameters(_)),
+            "{error}"
+        );
+        let mut params = params;
+        params["recursive"] = true.into();
+        let report = copy(params).await.unwrap();
+        assert_eq!(report["files"], 2);
+        assert_eq!(report["directories"], 2);
+        assert_eq!(report["symlinks"], 1);
+        assert_eq!(report["bytes"], 14);
+        assert_eq!(dir.read("dst/bin/script"), "#!/bin/sh\n");
+        let mode = fs::metadata(dir.join("dst/bin/script"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o750);
+        assert_eq!(
+            fs::read_link(dir.join("dst/link")).unwrap(),
+            Path::new("data")
+        );
+    }
+
+    #[tokio::test]
+    async fn stops_at_symlink_loops_before_copying() {
+        let dir = Scratch::new("copy-loop");
+        dir.write("src/a/file", "");
+        symlink(dir.join("src"), dir.join("src/a/up")).unwrap();
+        let error = copy(json!({
+            "source_path": dir.param("src"),
+            "destination_path": dir.param("dst"),
+            "recursive": true,
+            "symlinks": "follow",
+        }))
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("leads back"), "{error}");
+        assert!(!dir.join("dst").exists());
+    }
+
+    #[tokio::test]
+    async fn handles_existing_files_as_asked() {
+        let dir = Scratch::new("copy-existing");
+        dir.write("src/a", "new a");
+        dir.write("src/b", "new b");
+        dir.write("dst/b", "old b");
+        let params = |existing| {
+            json!({
+                "source_path": dir.param("src"),
+                "destination_path": dir.param("dst"),
+                "recursive": true,
+                "existing": existing,
+            })
+        };
+
+        // nothing is copied when one file exists
+        copy(params("error")).await.unwrap_err();
+        assert!(!dir.join("dst/a").exists());
+        let report = copy(params("skip")).await.unwrap();
+        assert_eq!(
+            (report["files"].clone(), report["skipped"].clone()),
+            (json!(1), json!(1))
+        );
+        assert_eq!(dir.read("dst/a"), "new a");
+        assert_eq!(dir.read("dst/b"), "old b");
+        let report = copy(params("overwrite")).await.unwrap();
+        assert_eq!(report["overwritten"], 2);
+        assert_eq!(dir.read("dst/b"), "new b");
+    }
+
+    #[test]
+    fn removes_what_it_created() {
+        let dir = Scratch::new("copy-remove");
+        dir.write("src/sub/file", "");
+        dir.write("src/other", "");
+        dir.write("dst/kept", "");
+        let mut copier = Copier::new(
+            PathJail::default(),
+            CopyOptions::archive(ExistingMode::Error),
+            false,
+        );
+        copier.copy(&dir.join("src"), &dir.join("dst")).unwrap();
+        assert!(dir.join("dst/sub/file").exists());
+        assert!(copier.remove_created().is_empty());
+        // the destination existed already
+        assert_eq!(
+            fs::read_dir(dir.join("dst"))
+                .unwrap()
+                .flatten()
+                .map(|e| e.file_name())
+                .collect::<Vec<_>>(),
+            ["kept"]
+        );
+    }
+}
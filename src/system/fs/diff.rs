@@ -0,0 +1,391 @@
+//! Unified diffs of file content.
+
+use std::{borrow::Cow, fmt::Write, sync::Arc};
+
+use mcp_core::{Content, handler::TypedToolHandler};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use similar::{Algorithm, DiffTag};
+
+use super::{FsContext, encoding};
+use crate::embed;
+
+/// Lines of context around each change, as `diff -u`.
+pub const DEFAULT_CONTEXT: usize = 3;
+
+/// Parameters for comparing a file with another file or with proposed content.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct DiffParams {
+    /// Path of the original file.
+    pub path: String,
+    /// Path of the file to compare `path` with.
+    pub other_path: Option<String>,
+    /// Proposed content of `path` to compare it with, instead of `other_path`.
+    pub content: Option<String>,
+    /// Lines of context around each change, 3 by default.
+    pub context: Option<usize>,
+    /// Whitespace differences to leave out of the diff.
+    pub ignore_whitespace: Option<IgnoreWhitespace>,
+}
+
+/// Whitespace differences a diff doesn't show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum IgnoreWhitespace {
+    /// Whitespace at the end of lines, as `diff -Z`.
+    Trailing,
+    /// Changes in the amount of whitespace, as `diff -b`.
+    Change,
+    /// All whitespace, as `diff -w`.
+    All,
+}
+
+impl IgnoreWhitespace {
+    /// What is left of a line to compare.
+    fn key(self, line: &str) -> Cow<'_, str> {
+        match self {
+            IgnoreWhitespace::Trailing => Cow::Borrowed(line.trim_end()),
+            IgnoreWhitespace::Change => {
+                let line = line.trim_end();
+                let mut key = String::with_capacity(line.len());
+                for (i, word) in line.split_whitespace().enumerate() {
+                    // leading whitespace is kept, however much of it
+                    if i > 0 || line.starts_with(char::is_whitespace) {
+                        key.push(' ');
+                    }
+                    key.push_str(word);
+                }
+                Cow::Owned(key)
+            }
+            IgnoreWhitespace::All => Cow::Owned(line.split_whitespace().collect()),
+        }
+    }
+}
+
+/// A unified diff and the number of lines it changes.
+#[derive(Debug, Default)]
+pub struct Diff {
+    pub text: String,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+/// Unified diff from `old` to `new`, empty when they are equal.
+pub fn unified_diff(
+    old: &str,
+    new: &str,
+    old_name: &str,
+    new_name: &str,
+    context: usize,
+) -> String {
+    diff(old, new, old_name, new_name, context, None).text
+}
+
+/// Unified diff from `old` to `new`, comparing lines without the whitespace differences
+/// `ignore` names. Unchanged lines are shown as they are in `old`.
+pub fn diff(
+    old: &str,
+    new: &str,
+    old_name: &str,
+    new_name: &str,
+    context: usize,
+    ignore: Option<IgnoreWhitespace>,
+) -> Diff {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let ops = similar::capture_diff_slices(
+        Algorithm::Myers,
+        &keys(&old_lines, ignore),
+        &keys(&new_lines, ignore),
+    );
+
+    let mut diff = Diff::default();
+    for group in similar::group_diff_ops(ops, context) {
+        if diff.text.is_empty() {
+            let _ = writeln!(diff.text, "--- {old_name}\n+++ {new_name}");
+        }
+        let (Some(first), Some(last)) = (group.first(), group.last()) else {
+            continue;
+        };
+        let old_start = first.old_range().start;
+        let new_start = first.new_range().start;
+        let _ = writeln!(
+            diff.text,
+            "@@ -{} +{} @@",
+            hunk_range(old_start, last.old_range().end - old_start),
+            hunk_range(new_start, last.new_range().end - new_start),
+        );
+        for op in &group {
+            let (tag, old_range, new_range) = op.as_tag_tuple();
+            if tag == DiffTag::Equal {
+                push_lines(&mut diff.text, ' ', &old_lines[old_range]);
+                continue;
+            }
+            diff.deletions += old_range.len();
+            diff.insertions += new_range.len();
+            push_lines(&mut diff.text, '-', &old_lines[old_range]);
+            push_lines(&mut diff.text, '+', &new_lines[new_range]);
+        }
+    }
+    diff
+}
+
+fn keys<'a>(lines: &[&'a str], ignore: Option<IgnoreWhitespace>) -> Vec<Cow<'a, str>> {
+    lines
+        .iter()
+        .map(|line| match ignore {
+            Some(ignore) => ignore.key(line),
+            None => Cow::Borrowed(*line),
+        })
+        .collect()
+}
+
+/// Line range of a hunk header, an empty range starts at the line before it.
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{len}", start + 1),
+    }
+}
+
+fn push_lines(text: &mut String, prefix: char, lines: &[&str]) {
+    for line in lines {
+        text.push(prefix);
+        text.push_str(line);
+        if !line.ends_with('\n') {
+            text.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffOutput {
+    /// Whether both contents are the same, byte for byte.
+    pub identical: bool,
+    /// Whether the diff is empty, the contents may still differ in ignored whitespace.
+    pub equivalent: bool,
+    /// Binary files are only compared byte for byte, without a diff.
+    pub binary: bool,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+pub struct FsDiff {
+    pub(super) ctx: Arc<FsContext>,
+}
+
+impl TypedToolHandler for FsDiff {
+    type Params = DiffParams;
+    fn name(&self) -> &'static str {
+        "fs.diff"
+    }
+
+    fn description(&self) -> &'static str {
+        embed!("tools/fs/diff")
+    }
+
+    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        let old = self.read(&params.path).await?;
+        let (new, new_name) = match (&params.other_path, params.content) {
+            (Some(other_path), None) => (self.read(other_path).await?, other_path.as_str()),
+            (None, Some(content)) => (content.into_bytes(), params.path.as_str()),
+            _ => {
+                return Err(mcp_core::ToolError::validation(
+                    "set either other_path or content",
+                ));
+            }
+        };
+
+        if encoding::is_binary(&old) || encoding::is_binary(&new) {
+            let output = DiffOutput {
+                identical: old == new,
+                equivalent: old == new,
+                binary: true,
+                insertions: 0,
+                deletions: 0,
+            };
+            return Ok(vec![Content::text(
+                serde_json::to_string(&output).expect("invalid json"),
+            )]);
+        }
+        let diff = diff(
+            &String::from_utf8_lossy(&old),
+            &String::from_utf8_lossy(&new),
+            &params.path,
+            new_name,
+            params.context.unwrap_or(DEFAULT_CONTEXT),
+            params.ignore_whitespace,
+        );
+        let output = DiffOutput {
+            identical: old == new,
+            equivalent: diff.text.is_empty(),
+            binary: false,
+            insertions: diff.insertions,
+            deletions: diff.deletions,
+        };
+        let output = Content::text(serde_json::to_string(&output).expect("invalid json"));
+        if diff.text.is_empty() {
+            return Ok(vec![output]);
+        }
+        Ok(vec![Content::text(diff.text), output])
+    }
+}
+
+impl FsDiff {
+    /// Read one of the compared files, refusing those larger than a read may be.
+    async fn read(&self, path: &str) -> mcp_core::ToolResult<Vec<u8>> {
+        let resolved = self.ctx.path(path).await?;
+        let size = tokio::fs::metadata(&resolved)
+            .await
+            .map_err(mcp_core::ToolError::execution)?
+            .len();
+        if size > self.ctx.max_read_bytes {
+            return Err(mcp_core::ToolError::ExecutionError(format!(
+                "{path} has {size} bytes, more than the {} bytes which can be compared",
+                self.ctx.max_read_bytes
+            )));
+        }
+        tokio::fs::read(&resolved)
+            .await
+            .map_err(mcp_core::ToolError::execution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::super::{
+        Fs,
+        testing::{Scratch, call, context},
+    };
+    use super::*;
+
+    fn fs_diff() -> FsDiff {
+        FsDiff {
+            ctx: context(Fs {
+                max_read_bytes: 100,
+                ..Fs::default()
+            }),
+        }
+    }
+
+    /// The diff, when there is one, and the result.
+    async fn compare(params: Value) -> (Option<String>, Value) {
+        let mut texts = call(&fs_diff(), params).await.unwrap();
+        let output = serde_json::from_str(&texts.pop().unwrap()).unwrap();
+        (texts.pop(), output)
+    }
+
+    #[test]
+    fn ignores_whitespace_as_asked() {
+        let diff = |old, new, ignore| diff(old, new, "a", "b", 3, ignore).text.is_empty();
+        assert!(!diff("a b\n", "a b \n", None));
+        assert!(diff("a b\n", "a b \n", Some(IgnoreWhitespace::Trailing)));
+        assert!(!diff("a b\n", "a  b\n", Some(IgnoreWhitespace::Trailing)));
+        assert!(diff("a b\n", "a  b \n", Some(IgnoreWhitespace::Change)));
+        // whitespace appearing where there was none is a change
+        assert!(!diff("ab\n", "a b\n", Some(IgnoreWhitespace::Change)));
+        assert!(!diff("a\n", "  a\n", Some(IgnoreWhitespace::Change)));
+        assert!(diff("ab\n", " a\tb\n", Some(IgnoreWhitespace::All)));
+    }
+
+    #[test]
+    fn shows_unchanged_lines_as_they_were() {
+        let diff = diff(
+            "a \nb\n",
+            "a\nc\n",
+            "old",
+            "new",
+            3,
+            Some(IgnoreWhitespace::Trailing),
+        );
+        assert_eq!(
+            diff.text,
+            "--- old\n+++ new\n@@ -1,2 +1,2 @@\n a \n-b\n+c\n"
+        );
+        assert_eq!((diff.insertions, diff.deletions), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn compares_with_another_file_or_content() {
+        let dir = Scratch::new("diff");
+        dir.write("old", "a\nb\n");
+        dir.write("new", "a\nc\n");
+        let (diff, output) =
+            compare(json!({"path": dir.param("old"), "other_path": dir.param("new")})).await;
+        let diff = diff.unwrap();
+        assert!(
+            diff.starts_with(&format!(
+                "--- {}\n+++ {}\n",
+                dir.param("old"),
+                dir.param("new")
+            )),
+            "{diff}"
+        );
+        assert_eq!(output["identical"], false);
+        assert_eq!(
+            (output["insertions"].clone(), output["deletions"].clone()),
+            (json!(1), json!(1))
+        );
+
+        let (diff, output) = compare(json!({"path": dir.param("old"), "content": "a\nb\n"})).await;
+        assert!(diff.is_none());
+        assert_eq!(output["identical"], true);
+
+        let error = call(&fs_diff(), json!({"path": dir.param("old")}))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, mcp_core::ToolError::InvalidParameters(_)),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn tells_identical_from_equivalent() {
+        let dir = Scratch::new("diff-equivalent");
+        dir.write("file", "a \n");
+        let (diff, output) = compare(json!({
+            "path": dir.param("file"),
+            "content": "a\n",
+            "ignore_whitespace": "trailing",
+        }))
+        .await;
+        assert!(diff.is_none());
+        assert_eq!(output["identical"], false);
+        assert_eq!(output["equivalent"], true);
+    }
+
+    #[tokio::test]
+    async fn compares_binary_files_byte_for_byte() {
+        let dir = Scratch::new("diff-binary");
+        dir.write("a", [0, 1]);
+        dir.write("b", [0, 2]);
+        let (diff, output) =
+            compare(json!({"path": dir.param("a"), "other_path": dir.param("b")})).await;
+        assert!(diff.is_none());
+        assert_eq!(output["binary"], true);
+        assert_eq!(output["identical"], false);
+        let (_, output) =
+            compare(json!({"path": dir.param("a"), "other_path": dir.param("a")})).await;
+        assert_eq!(output["identical"], true);
+    }
+
+    #[tokio::test]
+    async fn refuses_files_larger_than_a_read() {
+        let dir = Scratch::new("diff-large");
+        dir.write("small", "a\n");
+        dir.write("large", [b'a'; 101]);
+        for (path, other_path) in [("large", "small"), ("small", "large")] {
+            let error = call(
+                &fs_diff(),
+                json!({"path": dir.param(path), "other_path": dir.param(other_path)}),
+            )
+            .await
+            .unwrap_err();
+            assert!(error.to_string().contains("101 bytes"), "{error}");
+        }
+    }
+}
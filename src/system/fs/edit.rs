@@ -0,0 +1,203 @@
+//! Exact search-and-replace edits of text files.
+
+use std::sync::Arc;
+
+use mcp_core::{Content, handler::TypedToolHandler};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{FsContext, atomic, diff, journal::Target};
+use crate::{embed, system::mode};
+
+/// Parameters for editing a file.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct EditParams {
+    /// Path to the file to be edited.
+    pub path: String,
+    /// Exact text to be replaced, including whitespace and indentation. It must appear
+    /// exactly once unless `replace_all` is set, include more context to make it unique.
+    pub old_text: String,
+    /// Text to replace `old_text` with.
+    pub new_text: String,
+    /// Replace every occurrence of `old_text`.
+    #[serde(default)]
+    pub replace_all: bool,
+}
+
+pub struct FsEdit {
+    pub(super) ctx: Arc<FsContext>,
+}
+
+impl TypedToolHandler for FsEdit {
+    type Params = EditParams;
+    fn name(&self) -> &'static str {
+        "fs.edit"
+    }
+
+    fn description(&self) -> &'static str {
+        embed!("tools/fs/edit")
+    }
+
+    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        let path = self.ctx.path(&params.path).await?;
+        let _lock = atomic::lock(&path).await;
+        let content = tokio::fs::read(&path)
+            .await
+            .map_err(mcp_core::ToolError::execution)?;
+        let content = String::from_utf8(content).map_err(|_| {
+            mcp_core::ToolError::validation(format!("{} is not a utf-8 text file", params.path))
+        })?;
+        let (edited, replacements) = replace(
+            &content,
+            &params.old_text,
+            &params.new_text,
+            params.replace_all,
+        )
+        .map_err(mcp_core::ToolError::validation)?;
+        let diff = diff::unified_diff(
+            &content,
+            &edited,
+            &params.path,
+            &params.path,
+            diff::DEFAULT_CONTEXT,
+        );
+        if self.ctx.mode.guard(self.name())? {
+            return Ok(mode::dry_run(
+                self.name(),
+                json!({
+                    "path": params.path,
+                    "replacements": replacements,
+                    "diff": diff,
+                }),
+            ));
+        }
+        let snapshot = self
+            .ctx
+            .journal
+            .snapshot(vec![Target::Entry(path.clone())])
+            .await;
+        atomic::write(&path, edited.as_bytes())
+            .await
+            .map_err(mcp_core::ToolError::execution)?;
+        self.ctx.journal.record(self.name(), snapshot);
+        let output = json!({
+            "replacements": replacements,
+            "size": edited.len(),
+        });
+        Ok(vec![
+            Content::text(diff),
+            Content::text(serde_json::to_string(&output).expect("invalid json")),
+        ])
+    }
+}
+
+/// Replace `old` by `new` in `content`, returning the edited content and the number of
+/// replacements, fails when `old` is missing, or ambiguous unless `all` is set.
+pub fn replace(content: &str, old: &str, new: &str, all: bool) -> Result<(String, usize), String> {
+    if old.is_empty() {
+        return Err("old_text must not be empty".to_string());
+    }
+    let matches = content.matches(old).count();
+    match matches {
+        0 => Err("old_text was not found in the file".to_string()),
+        1 => Ok((content.replacen(old, new, 1), 1)),
+        _ if all => Ok((content.replace(old, new), matches)),
+        _ => Err(format!(
+            "old_text was found {matches} times, include more surrounding text to make it unique or set replace_all"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::super::{
+        Fs,
+        testing::{Scratch, call, context},
+    };
+    use super::*;
+
+    async fn edit(params: Value) -> mcp_core::ToolResult<Vec<String>> {
+        let tool = FsEdit {
+            ctx: context(Fs::default()),
+        };
+        call(&tool, params).await
+    }
+
+    #[test]
+    fn replaces_a_unique_match() {
+        assert_eq!(
+            replace("let a = 1;\nlet b = 2;\n", "b = 2", "b = 3", false),
+            Ok(("let a = 1;\nlet b = 3;\n".to_string(), 1))
+        );
+        // a single match is replaced with replace_all as well
+        assert_eq!(replace("abc", "b", "", true), Ok(("ac".to_string(), 1)));
+    }
+
+    #[test]
+    fn replaces_every_match_when_asked_to() {
+        assert_eq!(
+            replace("a.b.c", ".", "::", true),
+            Ok(("a::b::c".to_string(), 2))
+        );
+    }
+
+    #[test]
+    fn rejects_ambiguous_or_missing_text() {
+        assert_eq!(
+            replace("a.b.c", ".", "::", false).unwrap_err(),
+            "old_text was found 2 times, include more surrounding text to make it unique or set replace_all"
+        );
+        assert_eq!(
+            replace("abc", "x", "y", true).unwrap_err(),
+            "old_text was not found in the file"
+        );
+        assert_eq!(
+            replace("abc", "", "y", false).unwrap_err(),
+            "old_text must not be empty"
+        );
+    }
+
+    #[test]
+    fn counts_matches_without_overlap() {
+        assert_eq!(replace("aaa", "aa", "b", true), Ok(("ba".to_string(), 1)));
+    }
+
+    #[tokio::test]
+    async fn edits_a_file_and_returns_the_diff() {
+        let dir = Scratch::new("edit");
+        dir.write("file", "let a = 1;\nlet b = 2;\n");
+        let texts =
+            edit(json!({"path": dir.param("file"), "old_text": "b = 2", "new_text": "b = 3"}))
+                .await
+                .unwrap();
+        assert_eq!(dir.read("file"), "let a = 1;\nlet b = 3;\n");
+        assert!(
+            texts[0].contains("-let b = 2;\n+let b = 3;\n"),
+            "{}",
+            texts[0]
+        );
+        let output: Value = serde_json::from_str(&texts[1]).unwrap();
+        assert_eq!(output["replacements"], 1);
+    }
+
+    #[tokio::test]
+    async fn refuses_an_edit_whose_text_no_longer_matches() {
+        let dir = Scratch::new("edit-conflict");
+        dir.write("file", "let a = 1;\nlet a = 1;\n");
+        // the file changed since it was read, so old_text is missing or ambiguous
+        for old_text in ["let b = 2;", "let a = 1;"] {
+            let error =
+                edit(json!({"path": dir.param("file"), "old_text": old_text, "new_text": ""}))
+                    .await
+                    .unwrap_err();
+            assert!(
+                matches!(error, mcp_core::ToolError::InvalidParameters(_)),
+                "{error}"
+            );
+        }
+        assert_eq!(dir.read("file"), "let a = 1;\nlet a = 1;\n");
+    }
+}
@@ -0,0 +1,61 @@
+//! Text and binary representations of file content.
+
+use std::path::Path;
+
+use base64::Engine;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// How much of the content is inspected to tell binary from text, as git does.
+pub const SNIFF_LEN: usize = 8000;
+
+/// How file content is carried in a tool's text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    /// The content as text, invalid utf-8 is replaced.
+    Utf8,
+    /// The raw bytes of the content, encoded in base64.
+    Base64,
+}
+
+impl Encoding {
+    /// Choose `base64` for binary content and `utf8` for text.
+    pub fn detect(content: &[u8]) -> Self {
+        if is_binary(content) {
+            Encoding::Base64
+        } else {
+            Encoding::Utf8
+        }
+    }
+
+    pub fn encode(self, content: &[u8]) -> String {
+        match self {
+            Encoding::Utf8 => String::from_utf8_lossy(content).into_owned(),
+            Encoding::Base64 => base64::engine::general_purpose::STANDARD.encode(content),
+        }
+    }
+
+    pub fn decode(self, data: &str) -> Result<Vec<u8>, base64::DecodeError> {
+        match self {
+            Encoding::Utf8 => Ok(data.as_bytes().to_vec()),
+            Encoding::Base64 => base64::engine::general_purpose::STANDARD.decode(data),
+        }
+    }
+}
+
+/// Content is binary when it has a NUL byte or isn't valid utf-8, a character cut off at
+/// the end of the content doesn't count.
+pub fn is_binary(content: &[u8]) -> bool {
+    let sniffed = &content[..content.len().min(SNIFF_LEN)];
+    sniffed.contains(&0) || std::str::from_utf8(sniffed).is_err_and(|e| e.error_len().is_some())
+}
+
+/// MIME type of a file, guessed from its extension and whether its content is binary.
+pub fn mime_type(path: &Path, binary: bool) -> String {
+    match mime_guess::from_path(path).first() {
+        Some(mime) => mime.essence_str().to_string(),
+        None if binary => "application/octet-stream".to_string(),
+        None => "text/plain".to_string(),
+    }
+}
@@ -0,0 +1,200 @@
+//! Finding files by glob patterns.
+
+use std::{path::PathBuf, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use mcp_core::{Content, handler::TypedToolHandler};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    FsContext,
+    walk::{self, EntryType, WalkOptions},
+};
+use crate::embed;
+
+/// Entries returned unless the request says otherwise.
+pub const DEFAULT_MAX_RESULTS: usize = 1000;
+
+/// Parameters for finding files by glob patterns.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct GlobParams {
+    /// Globs matched against the paths relative to `path`, such as `src/**/*.rs`. `*` doesn't
+    /// match `/`, `**` matches any number of directories.
+    pub patterns: Vec<String>,
+    /// Directory to search in.
+    pub path: String,
+    /// Only return entries of this type.
+    pub file_type: Option<EntryType>,
+    /// Also return the size and modification time of the entries.
+    #[serde(default)]
+    pub details: bool,
+    /// Maximum number of entries returned, 1000 by default.
+    pub max_results: Option<usize>,
+    #[serde(flatten)]
+    pub walk: WalkOptions,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GlobEntry {
+    pub path: PathBuf,
+    #[serde(rename = "type")]
+    pub file_type: EntryType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct GlobOutput {
+    /// Matching entries, sorted by path.
+    pub entries: Vec<GlobEntry>,
+    /// Whether the search stopped at `max_results`, there may be more entries.
+    pub limit_reached: bool,
+}
+
+pub struct FsGlob {
+    pub(super) ctx: Arc<FsContext>,
+}
+
+impl TypedToolHandler for FsGlob {
+    type Params = GlobParams;
+    fn name(&self) -> &'static str {
+        "fs.glob"
+    }
+
+    fn description(&self) -> &'static str {
+        embed!("tools/fs/glob")
+    }
+
+    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        let root = self.ctx.path(&params.path).await?;
+        let patterns = glob_set(&params.patterns).map_err(mcp_core::ToolError::validation)?;
+        let walk = walk::walk(&self.ctx.jail, &root, &[], &params.walk)
+            .map_err(mcp_core::ToolError::validation)?;
+        let max_results = params.max_results.unwrap_or(DEFAULT_MAX_RESULTS);
+        let output = tokio::task::spawn_blocking(move || {
+            let mut output = GlobOutput::default();
+            for entry in walk.flatten() {
+                let Ok(relative) = entry.path().strip_prefix(&root) else {
+                    continue;
+                };
+                if entry.depth() == 0 || !patterns.is_match(relative) {
+                    continue;
+                }
+                let file_type = entry.file_type().map_or(EntryType::Other, EntryType::from);
+                if params.file_type.is_some_and(|wanted| wanted != file_type) {
+                    continue;
+                }
+                if output.entries.len() >= max_results {
+                    output.limit_reached = true;
+                    break;
+                }
+                let metadata = params.details.then(|| entry.metadata().ok()).flatten();
+                output.entries.push(GlobEntry {
+                    path: entry.into_path(),
+                    file_type,
+                    size: metadata.as_ref().map(|metadata| metadata.len()),
+                    modified: metadata
+                        .and_then(|metadata| metadata.modified().ok())
+                        .map(DateTime::from),
+                });
+            }
+            output
+        })
+        .await
+        .map_err(mcp_core::ToolError::execution)?;
+        Ok(vec![Content::text(
+            serde_json::to_string(&output).expect("invalid json"),
+        )])
+    }
+}
+
+fn glob_set(patterns: &[String]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = pattern.strip_prefix("./").unwrap_or(pattern);
+        builder.add(GlobBuilder::new(pattern).literal_separator(true).build()?);
+    }
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::super::{
+        Fs,
+        testing::{Scratch, call_json, context},
+    };
+    use super::*;
+
+    async fn glob(params: Value) -> Value {
+        let tool = FsGlob {
+            ctx: context(Fs::default()),
+        };
+        call_json(&tool, params).await.unwrap()
+    }
+
+    /// The paths of the entries found, relative to `dir`.
+    fn paths(dir: &Scratch, output: &Value) -> Vec<String> {
+        let entries = output["entries"].as_array().unwrap();
+        entries
+            .iter()
+            .map(|entry| {
+                let path = std::path::Path::new(entry["path"].as_str().unwrap());
+                path.strip_prefix(&**dir).unwrap().display().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_separators_literally() {
+        let set = glob_set(&["*.rs".to_string(), "./src/**/*.toml".to_string()]).unwrap();
+        assert!(set.is_match("main.rs"));
+        assert!(!set.is_match("src/main.rs"));
+        assert!(set.is_match("src/Cargo.toml"));
+        assert!(set.is_match("src/a/b/Cargo.toml"));
+        assert!(glob_set(&["a[".to_string()]).is_err());
+    }
+
+    #[tokio::test]
+    async fn finds_entries_by_pattern_and_type() {
+        let dir = Scratch::new("glob");
+        dir.write("src/main.rs", "fn main() {}\n");
+        dir.write("src/lib/mod.rs", "");
+        dir.write("README.md", "");
+        std::fs::create_dir(dir.join("src/empty.rs")).unwrap();
+        let output = glob(json!({"patterns": ["**/*.rs"], "path": dir.param("")})).await;
+        assert_eq!(
+            paths(&dir, &output),
+            ["src/empty.rs", "src/lib/mod.rs", "src/main.rs"]
+        );
+        assert!(output["entries"][0].get("size").is_none());
+
+        let output = glob(json!({
+            "patterns": ["src/*"],
+            "path": dir.param(""),
+            "file_type": "file",
+            "details": true,
+        }))
+        .await;
+        assert_eq!(paths(&dir, &output), ["src/main.rs"]);
+        assert_eq!(output["entries"][0]["type"], "file");
+        assert_eq!(output["entries"][0]["size"], 13);
+        assert!(output["entries"][0]["modified"].is_string());
+    }
+
+    #[tokio::test]
+    async fn stops_at_max_results() {
+        let dir = Scratch::new("glob-max");
+        dir.write("a", "");
+        dir.write("b", "");
+        let output =
+            glob(json!({"patterns": ["*"], "path": dir.param(""), "max_results": 1})).await;
+        assert_eq!(paths(&dir, &output), ["a"]);
+        assert_eq!(output["limit_reached"], true);
+    }
+}
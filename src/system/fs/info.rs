@@ -0,0 +1,253 @@
+//! Metadata of files, as `stat` reports it.
+
+use std::{
+    io::Read,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use mcp_core::{Content, handler::TypedToolHandler};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{FsContext, encoding, jail, link, walk::EntryType};
+use crate::embed;
+
+/// Parameters for retrieving file information.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct GetFileInfoParams {
+    /// Path to the file for which to get information.
+    pub path: Option<String>,
+    /// Paths of several files to get information for at once.
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// Report on the file a symlink points to rather than on the symlink itself.
+    #[serde(default)]
+    pub follow_symlinks: bool,
+}
+
+/// Metadata of a file, every field is always present and `null` when unknown.
+#[derive(Debug, Serialize)]
+pub struct FileInfo {
+    /// Path as requested.
+    pub path: String,
+    /// Absolute path the metadata is about.
+    pub resolved_path: PathBuf,
+    #[serde(rename = "type")]
+    pub file_type: EntryType,
+    pub size: u64,
+    /// Permission bits, in octal.
+    pub mode: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub user: Option<String>,
+    pub group: Option<String>,
+    /// Number of hard links.
+    pub nlink: u64,
+    pub inode: u64,
+    pub device: u64,
+    pub accessed: Option<DateTime<Utc>>,
+    pub modified: Option<DateTime<Utc>>,
+    /// When the metadata last changed.
+    pub changed: Option<DateTime<Utc>>,
+    /// When the file was created, not every file system records it.
+    pub created: Option<DateTime<Utc>>,
+    /// Where the symlink at `path` points to.
+    pub symlink_target: Option<PathBuf>,
+    /// Whether `path` is a symlink which leads to nothing.
+    pub dangling: bool,
+    pub mime_type: Option<String>,
+}
+
+/// Information about a file, or why it can't be had.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum FileInfoResult {
+    Info(Box<FileInfo>),
+    Error { path: String, error: String },
+}
+
+pub struct FsGetFileInfo {
+    pub(super) ctx: Arc<FsContext>,
+}
+
+impl TypedToolHandler for FsGetFileInfo {
+    type Params = GetFileInfoParams;
+    fn name(&self) -> &'static str {
+        "fs.get_file_info"
+    }
+
+    fn description(&self) -> &'static str {
+        embed!("tools/fs/info")
+    }
+
+    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        let paths: Vec<String> = params.path.into_iter().chain(params.paths).collect();
+        if paths.is_empty() {
+            return Err(mcp_core::ToolError::validation("no path given"));
+        }
+        let mut files = Vec::with_capacity(paths.len());
+        for path in paths {
+            let info = match self.info(&path, params.follow_symlinks).await {
+                Ok(info) => FileInfoResult::Info(Box::new(info)),
+                Err(error) => FileInfoResult::Error { path, error },
+            };
+            files.push(info);
+        }
+        Ok(vec![Content::text(
+            serde_json::json!({ "files": files }).to_string(),
+        )])
+    }
+}
+
+impl FsGetFileInfo {
+    async fn info(&self, path: &str, follow_symlinks: bool) -> Result<FileInfo, String> {
+        let entry = self.ctx.entry(path).await.map_err(|e| e.to_string())?;
+        let jail = self.ctx.jail.clone();
+        let requested = path.to_string();
+        tokio::task::spawn_blocking(move || {
+            // the final target of the link, which must be in the jail as well
+            let resolved = if follow_symlinks {
+                jail::resolve(&entry).map_err(|e| e.to_string())?
+            } else {
+                entry.clone()
+            };
+            jail.check(&resolved, false).map_err(|e| e.to_string())?;
+            stat(requested, &entry, resolved).map_err(|e| e.to_string())
+        })
+        .await
+        .map_err(|e| e.to_string())?
+    }
+}
+
+/// Metadata of `resolved`, which is `entry` itself or where the symlink at `entry` leads.
+fn stat(path: String, entry: &Path, resolved: PathBuf) -> std::io::Result<FileInfo> {
+    let metadata = std::fs::symlink_metadata(&resolved)?;
+    let file_type = EntryType::from(metadata.file_type());
+    let symlink_target = match std::fs::symlink_metadata(entry) {
+        Ok(metadata) if metadata.file_type().is_symlink() => std::fs::read_link(entry).ok(),
+        _ => None,
+    };
+    Ok(FileInfo {
+        mime_type: mime_type(&resolved, file_type),
+        resolved_path: resolved,
+        file_type,
+        size: metadata.len(),
+        mode: format!("{:o}", metadata.permissions().mode() & 0o7777),
+        uid: metadata.uid(),
+        gid: metadata.gid(),
+        user: uzers::get_user_by_uid(metadata.uid())
+            .map(|user| user.name().to_string_lossy().into_owned()),
+        group: uzers::get_group_by_gid(metadata.gid())
+            .map(|group| group.name().to_string_lossy().into_owned()),
+        nlink: metadata.nlink(),
+        inode: metadata.ino(),
+        device: metadata.dev(),
+        accessed: metadata.accessed().ok().map(DateTime::from),
+        modified: metadata.modified().ok().map(DateTime::from),
+        changed: DateTime::from_timestamp(metadata.ctime(), metadata.ctime_nsec() as u32),
+        created: metadata.created().ok().map(DateTime::from),
+        dangling: symlink_target.is_some() && link::is_dangling(entry),
+        symlink_target,
+        path,
+    })
+}
+
+/// MIME type of a regular file from its extension and content, directories and symlinks
+/// get the `inode/*` types `file --mime-type` reports.
+pub(super) fn mime_type(path: &Path, file_type: EntryType) -> Option<String> {
+    match file_type {
+        EntryType::File => {
+            let mut head = Vec::with_capacity(encoding::SNIFF_LEN);
+            std::fs::File::open(path)
+                .and_then(|file| file.take(encoding::SNIFF_LEN as u64).read_to_end(&mut head))
+                .ok()?;
+            Some(encoding::mime_type(path, encoding::is_binary(&head)))
+        }
+        EntryType::Dir => Some("inode/directory".to_string()),
+        EntryType::Symlink => Some("inode/symlink".to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use serde_json::{Value, json};
+
+    use super::super::{
+        Fs,
+        testing::{Scratch, call_json, context},
+    };
+    use super::*;
+
+    async fn info(params: Value) -> mcp_core::ToolResult<Value> {
+        let tool = FsGetFileInfo {
+            ctx: context(Fs::default()),
+        };
+        call_json(&tool, params).await
+    }
+
+    #[tokio::test]
+    async fn reports_dangling_symlinks() {
+        let dir = Scratch::new("info-dangling");
+        symlink("missing", dir.join("link")).unwrap();
+        let output = info(json!({"path": dir.param("link")})).await.unwrap();
+        let file = &output["files"][0];
+        assert_eq!(file["type"], "symlink");
+        assert_eq!(file["dangling"], true);
+        assert_eq!(file["symlink_target"], "missing");
+        assert_eq!(file["resolved_path"], dir.param("link"));
+        // there is nothing to follow the link to
+        let output = info(json!({"path": dir.param("link"), "follow_symlinks": true}))
+            .await
+            .unwrap();
+        assert_eq!(output["files"][0]["path"], dir.param("link"));
+        assert!(output["files"][0]["error"].is_string());
+    }
+
+    #[tokio::test]
+    async fn follows_symlinks_when_asked() {
+        let dir = Scratch::new("info-follow");
+        dir.write("file", "abc");
+        symlink("file", dir.join("link")).unwrap();
+        let output = info(json!({"path": dir.param("link"), "follow_symlinks": true}))
+            .await
+            .unwrap();
+        let file = &output["files"][0];
+        assert_eq!(file["type"], "file");
+        assert_eq!(file["size"], 3);
+        assert_eq!(file["resolved_path"], dir.param("file"));
+        assert_eq!(file["symlink_target"], "file");
+        assert_eq!(file["dangling"], false);
+    }
+
+    #[tokio::test]
+    async fn reports_errors_per_path() {
+        let dir = Scratch::new("info-errors");
+        dir.write("file", "");
+        let output = info(json!({
+            "path": dir.param("file"),
+            "paths": [dir.param("missing"), dir.param("")],
+        }))
+        .await
+        .unwrap();
+        let files = output["files"].as_array().unwrap();
+        assert_eq!(files.len(), 3);
+        assert_eq!(files[0]["type"], "file");
+        assert_eq!(files[1]["path"], dir.param("missing"));
+        assert!(files[1]["error"].is_string());
+        assert!(files[1].get("type").is_none());
+        assert_eq!(files[2]["type"], "dir");
+        assert_eq!(files[2]["mime_type"], "inode/directory");
+
+        let error = info(json!({})).await.unwrap_err();
+        assert!(
+            matches!(error, mcp_core::ToolError::InvalidParameters(_)),
+            "{error}"
+        );
+    }
+}
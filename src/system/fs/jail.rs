@@ -0,0 +1,335 @@
+//! Confinement of the `fs.*` tools to configured root directories.
+//!
+//! Paths are resolved the way the kernel would resolve them, following every symlink,
+//! including dangling ones, before they are checked against the roots and deny patterns.
+//! The tools then operate on the resolved path, so neither `..` nor a symlink can lead
+//! out of the jail.
+
+use std::{
+    collections::VecDeque,
+    ffi::OsString,
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// Maximum number of symlinks followed while resolving a path, as `MAXSYMLINKS` on linux.
+const MAX_SYMLINKS: usize = 40;
+
+#[derive(Debug, Clone, Default)]
+pub struct PathJail {
+    roots: Vec<PathBuf>,
+    deny: GlobSet,
+}
+
+#[derive(Debug)]
+pub enum JailError {
+    Io(io::Error),
+    OutsideRoots(PathBuf),
+    Denied(PathBuf),
+}
+
+impl std::fmt::Display for JailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JailError::Io(e) => write!(f, "{e}"),
+            JailError::OutsideRoots(path) => {
+                write!(f, "{} is outside of the allowed roots", path.display())
+            }
+            JailError::Denied(path) => write!(f, "access to {} is denied", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for JailError {}
+
+impl From<io::Error> for JailError {
+    fn from(value: io::Error) -> Self {
+        JailError::Io(value)
+    }
+}
+
+impl PathJail {
+    /// Confine paths to `roots`, any path is allowed when it's empty, and deny paths below
+    /// any of the `deny` glob patterns (e.g. `/etc/shadow`, `/proc/*/mem`).
+    pub fn new(roots: &[PathBuf], deny: &[String]) -> io::Result<Self> {
+        let roots = roots
+            .iter()
+            .map(std::fs::canonicalize)
+            .collect::<io::Result<Vec<_>>>()?;
+        let mut deny_set = GlobSetBuilder::new();
+        for pattern in deny {
+            deny_set.add(glob(pattern)?);
+        }
+        let deny = deny_set
+            .build()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        Ok(PathJail { roots, deny })
+    }
+
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// Whether a resolved path matches a deny pattern itself, regardless of its ancestors.
+    /// Used while walking a directory tree, where the ancestors were already checked.
+    pub fn is_denied(&self, path: &Path) -> bool {
+        self.deny.is_match(path)
+    }
+
+    /// Resolve a path the tools will follow symlinks of, and check it against the jail.
+    pub async fn resolve(&self, path: impl AsRef<Path>) -> Result<PathBuf, JailError> {
+        self.resolve_async(path.as_ref(), true).await
+    }
+
+    /// Resolve a path whose last component is operated on itself even when it's a symlink,
+    /// such as the path of a removed or renamed entry, and check it against the jail.
+    pub async fn resolve_entry(&self, path: impl AsRef<Path>) -> Result<PathBuf, JailError> {
+        self.resolve_async(path.as_ref(), false).await
+    }
+
+    async fn resolve_async(&self, path: &Path, follow: bool) -> Result<PathBuf, JailError> {
+        let jail = self.clone();
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || jail.check(&path, follow))
+            .await
+            .map_err(|e| JailError::Io(io::Error::other(e)))?
+    }
+
+    /// Resolve and check a path, blocking on the file system. The path is resolved even
+    /// without roots or deny patterns, so tools always operate on the final target.
+    pub fn check(&self, path: &Path, follow: bool) -> Result<PathBuf, JailError> {
+        let resolved = if follow {
+            resolve(path)?
+        } else {
+            let path = std::path::absolute(path)?;
+            match (path.parent(), path.file_name()) {
+                (Some(parent), Some(name)) => resolve(parent)?.join(name),
+                _ => resolve(&path)?,
+            }
+        };
+        if !self.roots.is_empty() && !self.roots.iter().any(|root| resolved.starts_with(root)) {
+            return Err(JailError::OutsideRoots(resolved));
+        }
+        if resolved
+            .ancestors()
+            .any(|ancestor| self.deny.is_match(ancestor))
+        {
+            return Err(JailError::Denied(resolved));
+        }
+        Ok(resolved)
+    }
+}
+
+fn glob(pattern: &str) -> io::Result<Glob> {
+    GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// Resolve `path` to an absolute path without `.`, `..` or symlinks, like the kernel would.
+///
+/// Unlike [`std::fs::canonicalize`], the path doesn't need to exist, so the target of a
+/// file about to be created, or of a dangling symlink, is resolved as well.
+pub fn resolve(path: &Path) -> io::Result<PathBuf> {
+    let mut pending = VecDeque::new();
+    push_steps(&mut pending, &std::path::absolute(path)?);
+    let mut resolved = PathBuf::from("/");
+    let mut symlinks = 0;
+    while let Some(step) = pending.pop_front() {
+        match step {
+            Step::Root => resolved = PathBuf::from("/"),
+            Step::Parent => {
+                resolved.pop();
+            }
+            Step::Name(name) => {
+                let candidate = resolved.join(&name);
+                match std::fs::symlink_metadata(&candidate) {
+                    Ok(metadata) if metadata.file_type().is_symlink() => {
+                        symlinks += 1;
+                        if symlinks > MAX_SYMLINKS {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                format!("too many levels of symbolic links in {}", path.display()),
+                            ));
+                        }
+                        let target = std::fs::read_link(&candidate)?;
+                        let mut target_steps = VecDeque::new();
+                        push_steps(&mut target_steps, &target);
+                        target_steps.extend(pending);
+                        pending = target_steps;
+                    }
+                    _ => resolved = candidate,
+                }
+            }
+        }
+    }
+    Ok(resolved)
+}
+
+enum Step {
+    Root,
+    Parent,
+    Name(OsString),
+}
+
+fn push_steps(pending: &mut VecDeque<Step>, path: &Path) {
+    pending.extend(path.components().filter_map(|component| match component {
+        Component::Prefix(_) | Component::RootDir => Some(Step::Root),
+        Component::CurDir => None,
+        Component::ParentDir => Some(Step::Parent),
+        Component::Normal(name) => Some(Step::Name(name.to_owned())),
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+    use crate::system::fs::testing::Scratch;
+
+    fn jail(roots: &[&Path], deny: &[&str]) -> PathJail {
+        let roots = roots
+            .iter()
+            .map(|root| root.to_path_buf())
+            .collect::<Vec<_>>();
+        let deny = deny
+            .iter()
+            .map(|pattern| pattern.to_string())
+            .collect::<Vec<_>>();
+        PathJail::new(&roots, &deny).unwrap()
+    }
+
+    #[test]
+    fn removes_dot_and_parent_components() {
+        let dir = Scratch::new("dots");
+        std::fs::create_dir(dir.join("a")).unwrap();
+        assert_eq!(
+            resolve(&dir.join("a/./../a/b/../c")).unwrap(),
+            dir.join("a/c")
+        );
+        assert_eq!(
+            resolve(Path::new("/../../etc")).unwrap(),
+            PathBuf::from("/etc")
+        );
+    }
+
+    #[test]
+    fn parent_traversal_cannot_leave_a_root() {
+        let dir = Scratch::new("traversal");
+        std::fs::create_dir(dir.join("root")).unwrap();
+        let jail = jail(&[&dir.join("root")], &[]);
+        assert!(jail.check(&dir.join("root/a/../b"), true).is_ok());
+        assert!(matches!(
+            jail.check(&dir.join("root/../outside"), true),
+            Err(JailError::OutsideRoots(path)) if path == dir.join("outside")
+        ));
+    }
+
+    #[test]
+    fn follows_symlinks_out_of_a_root() {
+        let dir = Scratch::new("escape");
+        std::fs::create_dir(dir.join("root")).unwrap();
+        std::fs::create_dir(dir.join("outside")).unwrap();
+        symlink(dir.join("outside"), dir.join("root/link")).unwrap();
+        let jail = jail(&[&dir.join("root")], &[]);
+        assert!(matches!(
+            jail.check(&dir.join("root/link/file"), true),
+            Err(JailError::OutsideRoots(path)) if path == dir.join("outside/file")
+        ));
+        // The link itself is inside the root when it isn't followed.
+        assert_eq!(
+            jail.check(&dir.join("root/link"), false).unwrap(),
+            dir.join("root/link")
+        );
+        assert!(jail.check(&dir.join("root/link"), true).is_err());
+    }
+
+    #[test]
+    fn resolves_relative_and_dangling_symlinks() {
+        let dir = Scratch::new("dangling");
+        std::fs::create_dir(dir.join("sub")).unwrap();
+        symlink("../missing/file", dir.join("sub/link")).unwrap();
+        assert_eq!(
+            resolve(&dir.join("sub/link")).unwrap(),
+            dir.join("missing/file")
+        );
+        symlink(dir.join("sub/link"), dir.join("chain")).unwrap();
+        assert_eq!(
+            resolve(&dir.join("chain")).unwrap(),
+            dir.join("missing/file")
+        );
+    }
+
+    #[test]
+    fn stops_at_symlink_loops() {
+        let dir = Scratch::new("loop");
+        symlink(dir.join("b"), dir.join("a")).unwrap();
+        symlink(dir.join("a"), dir.join("b")).unwrap();
+        let error = resolve(&dir.join("a")).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("too many levels of symbolic links")
+        );
+
+        // A chain of exactly `MAX_SYMLINKS` links still resolves.
+        std::fs::write(dir.join("target"), "").unwrap();
+        let mut previous = dir.join("target");
+        for i in 0..MAX_SYMLINKS {
+            let link = dir.join(format!("chain{i}"));
+            symlink(&previous, &link).unwrap();
+            previous = link;
+        }
+        assert_eq!(resolve(&previous).unwrap(), dir.join("target"));
+        symlink(&previous, dir.join("chain_too_long")).unwrap();
+        assert!(resolve(&dir.join("chain_too_long")).is_err());
+    }
+
+    #[test]
+    fn denies_paths_below_a_denied_ancestor() {
+        let dir = Scratch::new("deny");
+        let pattern = format!("{}/secret*", dir.display());
+        let jail = jail(&[], &[&pattern]);
+        assert!(matches!(
+            jail.check(&dir.join("secrets/nested/file"), true),
+            Err(JailError::Denied(_))
+        ));
+        assert!(jail.check(&dir.join("public/file"), true).is_ok());
+        // `*` doesn't cross a separator, so only the matching ancestor is denied.
+        assert!(jail.is_denied(&dir.join("secret")));
+        assert!(!jail.is_denied(&dir.join("secret/file")));
+    }
+
+    #[test]
+    fn denies_symlinks_into_a_denied_directory() {
+        let dir = Scratch::new("deny-link");
+        std::fs::create_dir(dir.join("private")).unwrap();
+        symlink(dir.join("private"), dir.join("public")).unwrap();
+        let pattern = format!("{}/private", dir.display());
+        let jail = jail(&[], &[&pattern]);
+        assert!(matches!(
+            jail.check(&dir.join("public/key"), true),
+            Err(JailError::Denied(path)) if path == dir.join("private/key")
+        ));
+    }
+
+    #[tokio::test]
+    async fn resolves_without_roots_or_deny_patterns() {
+        let dir = Scratch::new("unrestricted");
+        std::fs::write(dir.join("target"), "").unwrap();
+        symlink(dir.join("target"), dir.join("link")).unwrap();
+        let jail = PathJail::default();
+        assert_eq!(
+            jail.resolve(dir.join("link")).await.unwrap(),
+            dir.join("target")
+        );
+        assert_eq!(
+            jail.resolve_entry(dir.join("sub/../link")).await.unwrap(),
+            dir.join("link")
+        );
+    }
+}
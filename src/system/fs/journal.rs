@@ -0,0 +1,813 @@
+//! The changes made by the `fs.*` tools of a session, and how to undo them.
+//!
+//! Before a tool changes anything, the prior state of every path it touches is snapshotted:
+//! the content, permissions, owner and modification time of files, the target of symlinks,
+//! and the whole tree of a directory it removes or copies into. `fs.undo` puts the paths
+//! back as they were. The journal is bounded, and a change whose files are too large is
+//! journaled without its snapshot, so that it's listed but can't be undone.
+
+use std::{
+    collections::VecDeque,
+    ffi::OsString,
+    fs::{self, FileTimes, Metadata},
+    io,
+    os::unix::fs::{MetadataExt, PermissionsExt, lchown},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use chrono::{DateTime, Utc};
+use mcp_core::{Content, handler::TypedToolHandler};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{FsContext, atomic, jail::PathJail, rename};
+use crate::{embed, system::mode};
+
+/// Default maximum number of changes kept in the journal of a session.
+pub const DEFAULT_MAX_ENTRIES: usize = 100;
+/// Default maximum number of bytes of file content kept in the journal of a session.
+pub const DEFAULT_MAX_BYTES: u64 = 64 * 1024 * 1024;
+/// Default size above which a file isn't snapshotted.
+pub const DEFAULT_MAX_FILE_BYTES: u64 = 8 * 1024 * 1024;
+
+/// Limits of the journal of each session.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct JournalConfig {
+    /// Maximum number of changes kept, the oldest ones are forgotten first.
+    pub max_entries: usize,
+    /// Maximum number of bytes of file content kept, for all the changes together.
+    pub max_bytes: u64,
+    /// Changes touching a file larger than this can't be undone.
+    pub max_file_bytes: u64,
+}
+
+impl Default for JournalConfig {
+    fn default() -> Self {
+        JournalConfig {
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_bytes: DEFAULT_MAX_BYTES,
+            max_file_bytes: DEFAULT_MAX_FILE_BYTES,
+        }
+    }
+}
+
+/// A path a change is about to touch.
+#[derive(Debug)]
+pub enum Target {
+    /// The entry at the path, without what's below a directory.
+    Entry(PathBuf),
+    /// Only the permissions and owner of the entry at the path.
+    Metadata(PathBuf),
+    /// The entry at the path and everything below it.
+    Tree(PathBuf),
+    /// An entry moved from `from` to `to`, replacing what's at `to`.
+    Moved { from: PathBuf, to: PathBuf },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Depth {
+    Metadata,
+    Entry,
+    Tree,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Attributes {
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    modified: Option<SystemTime>,
+}
+
+impl Attributes {
+    fn of(metadata: &Metadata) -> Self {
+        Attributes {
+            mode: metadata.mode(),
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            modified: metadata.modified().ok(),
+        }
+    }
+}
+
+/// What was at a path before a change.
+#[derive(Debug)]
+enum Prior {
+    Missing,
+    File {
+        /// `None` when the change keeps the content.
+        content: Option<Vec<u8>>,
+        attributes: Attributes,
+    },
+    Symlink {
+        target: PathBuf,
+        uid: u32,
+        gid: u32,
+    },
+    Directory {
+        attributes: Attributes,
+        /// `None` when the change keeps what's below the directory.
+        entries: Option<Vec<(OsString, Prior)>>,
+    },
+}
+
+/// How to revert part of a change, the steps of a change are reverted last first.
+#[derive(Debug)]
+enum Step {
+    Restore { path: PathBuf, prior: Prior },
+    Move { from: PathBuf, to: PathBuf },
+}
+
+impl Step {
+    fn paths(&self) -> Vec<&Path> {
+        match self {
+            Step::Restore { path, .. } => vec![path],
+            Step::Move { from, to } => vec![from, to],
+        }
+    }
+}
+
+/// The prior state of the paths of a change, taken before the change is made.
+#[derive(Debug)]
+pub struct Snapshot {
+    paths: Vec<PathBuf>,
+    steps: Vec<Step>,
+    bytes: u64,
+    /// Why the change can't be undone.
+    skipped: Option<String>,
+}
+
+impl Snapshot {
+    /// Snapshot `targets`, blocking on the file system.
+    fn capture(config: &JournalConfig, targets: Vec<Target>) -> Self {
+        let mut capture = Capture { config, bytes: 0 };
+        let mut snapshot = Snapshot {
+            paths: Vec::new(),
+            steps: Vec::new(),
+            bytes: 0,
+            skipped: None,
+        };
+        for target in targets {
+            let steps = match target {
+                Target::Entry(path) => {
+                    snapshot.paths.push(path.clone());
+                    capture.restore(&path, Depth::Entry).map(|step| vec![step])
+                }
+                Target::Metadata(path) => {
+                    snapshot.paths.push(path.clone());
+                    capture
+                        .restore(&path, Depth::Metadata)
+                        .map(|step| vec![step])
+                }
+                Target::Tree(path) => {
+                    snapshot.paths.push(path.clone());
+                    capture.restore(&path, Depth::Tree).map(|step| vec![step])
+                }
+                Target::Moved { from, to } => {
+                    snapshot.paths.extend([from.clone(), to.clone()]);
+                    // the entry is moved back before what it replaced is restored
+                    capture
+                        .restore(&to, Depth::Tree)
+                        .map(|step| vec![step, Step::Move { from, to }])
+                }
+            };
+            // the paths of a change which can't be undone are still listed
+            match steps {
+                Ok(steps) if snapshot.skipped.is_none() => snapshot.steps.extend(steps),
+                Ok(_) => {}
+                Err(reason) => {
+                    snapshot.skipped.get_or_insert(reason);
+                }
+            }
+        }
+        if snapshot.skipped.is_some() {
+            snapshot.steps.clear();
+        } else {
+            snapshot.bytes = capture.bytes;
+        }
+        snapshot
+    }
+}
+
+struct Capture<'a> {
+    config: &'a JournalConfig,
+    bytes: u64,
+}
+
+impl Capture<'_> {
+    fn restore(&mut self, path: &Path, depth: Depth) -> Result<Step, String> {
+        let prior = self.prior(path, depth)?;
+        // a missing path may be created along with its missing parents
+        let path = match prior {
+            Prior::Missing => missing_root(path),
+            _ => path,
+        };
+        Ok(Step::Restore {
+            path: path.to_path_buf(),
+            prior,
+        })
+    }
+
+    fn prior(&mut self, path: &Path, depth: Depth) -> Result<Prior, String> {
+        let metadata = match fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Prior::Missing),
+            Err(e) => return Err(format!("can't snapshot {}: {e}", path.display())),
+        };
+        let attributes = Attributes::of(&metadata);
+        let file_type = metadata.file_type();
+        if file_type.is_symlink() {
+            let target = fs::read_link(path)
+                .map_err(|e| format!("can't snapshot {}: {e}", path.display()))?;
+            return Ok(Prior::Symlink {
+                target,
+                uid: metadata.uid(),
+                gid: metadata.gid(),
+            });
+        }
+        if file_type.is_dir() {
+            let entries = match depth {
+                Depth::Tree => Some(self.entries(path)?),
+                Depth::Entry | Depth::Metadata => None,
+            };
+            return Ok(Prior::Directory {
+                attributes,
+                entries,
+            });
+        }
+        let content = match depth {
+            Depth::Metadata => None,
+            Depth::Entry | Depth::Tree if file_type.is_file() => {
+                Some(self.content(path, &metadata)?)
+            }
+            Depth::Entry | Depth::Tree => {
+                return Err(format!(
+                    "{} is not a regular file, it can't be snapshotted",
+                    path.display()
+                ));
+            }
+        };
+        Ok(Prior::File {
+            content,
+            attributes,
+        })
+    }
+
+    fn entries(&mut self, path: &Path) -> Result<Vec<(OsString, Prior)>, String> {
+        let entries = fs::read_dir(path)
+            .and_then(|entries| entries.collect::<io::Result<Vec<_>>>())
+            .map_err(|e| format!("can't snapshot {}: {e}", path.display()))?;
+        entries
+            .into_iter()
+            .map(|entry| Ok((entry.file_name(), self.prior(&entry.path(), Depth::Tree)?)))
+            .collect()
+    }
+
+    fn content(&mut self, path: &Path, metadata: &Metadata) -> Result<Vec<u8>, String> {
+        if metadata.len() > self.config.max_file_bytes {
+            return Err(format!(
+                "{} is larger than {} bytes",
+                path.display(),
+                self.config.max_file_bytes
+            ));
+        }
+        if self.bytes + metadata.len() > self.config.max_bytes {
+            return Err(format!(
+                "the files of the change are larger than {} bytes",
+                self.config.max_bytes
+            ));
+        }
+        let content =
+            fs::read(path).map_err(|e| format!("can't snapshot {}: {e}", path.display()))?;
+        self.bytes += content.len() as u64;
+        Ok(content)
+    }
+}
+
+/// The highest missing ancestor of a missing `path`, or `path` itself.
+fn missing_root(path: &Path) -> &Path {
+    let mut root = path;
+    while let Some(parent) = root.parent() {
+        if fs::symlink_metadata(parent).is_ok() {
+            break;
+        }
+        root = parent;
+    }
+    root
+}
+
+/// Put back what was at `path`, blocking on the file system.
+fn restore(path: &Path, prior: &Prior) -> io::Result<()> {
+    let current = fs::symlink_metadata(path).ok();
+    let is_dir = current.as_ref().is_some_and(Metadata::is_dir);
+    match prior {
+        Prior::Missing => {
+            if current.is_some() {
+                remove(path, is_dir)?;
+            }
+        }
+        Prior::File {
+            content,
+            attributes,
+        } => {
+            if let Some(content) = content {
+                if is_dir {
+                    fs::remove_dir_all(path)?;
+                }
+                create_parents(path)?;
+                write(path, content)?;
+            }
+            set_attributes(path, attributes)?;
+        }
+        Prior::Symlink { target, uid, gid } => {
+            if current.is_some() {
+                remove(path, is_dir)?;
+            }
+            create_parents(path)?;
+            std::os::unix::fs::symlink(target, path)?;
+            ignore_denied(lchown(path, Some(*uid), Some(*gid)))?;
+        }
+        Prior::Directory {
+            attributes,
+            entries,
+        } => {
+            if current.is_some() && !is_dir {
+                fs::remove_file(path)?;
+            }
+            if !is_dir {
+                fs::create_dir_all(path)?;
+            }
+            if let Some(entries) = entries {
+                // entries added since are removed, the others restored
+                for entry in fs::read_dir(path)? {
+                    let entry = entry?;
+                    let name = entry.file_name();
+                    if !entries.iter().any(|(prior, _)| *prior == name) {
+                        remove(&entry.path(), entry.file_type()?.is_dir())?;
+                    }
+                }
+                for (name, prior) in entries {
+                    restore(&path.join(name), prior)?;
+                }
+            }
+            // after the entries, whose changes update the modification time
+            set_attributes(path, attributes)?;
+        }
+    }
+    Ok(())
+}
+
+fn remove(path: &Path, is_dir: bool) -> io::Result<()> {
+    if is_dir {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+fn create_parents(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) => fs::create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
+/// Replace the content of `path` as [`atomic::write`] does, blocking.
+fn write(path: &Path, content: &[u8]) -> io::Result<()> {
+    let temp = atomic::temp_path(path);
+    let result = fs::write(&temp, content).and_then(|()| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn set_attributes(path: &Path, attributes: &Attributes) -> io::Result<()> {
+    if let Some(modified) = attributes.modified {
+        // the file may not be readable by the server, before its permissions are restored
+        if let Ok(file) = fs::File::open(path) {
+            let _ = file.set_times(FileTimes::new().set_modified(modified));
+        }
+    }
+    // only root may give files away
+    ignore_denied(lchown(path, Some(attributes.uid), Some(attributes.gid)))?;
+    // after chown, which clears the setuid and setgid bits
+    fs::set_permissions(path, fs::Permissions::from_mode(attributes.mode))
+}
+
+fn ignore_denied(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Ok(()),
+        result => result,
+    }
+}
+
+/// Revert the steps of a change, last first, blocking on the file system.
+fn revert(jail: &PathJail, steps: &[Step]) -> io::Result<()> {
+    for step in steps.iter().rev() {
+        match step {
+            Step::Restore { path, prior } => restore(path, prior)?,
+            Step::Move { from, to } => {
+                if fs::symlink_metadata(from).is_ok() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!(
+                            "{} exists again, {} can't be moved back",
+                            from.display(),
+                            to.display()
+                        ),
+                    ));
+                }
+                match fs::rename(to, from) {
+                    Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                        rename::move_across_devices(jail.clone(), to, from, false, false)?;
+                    }
+                    result => result?,
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+struct Entry {
+    id: u64,
+    tool: &'static str,
+    time: DateTime<Utc>,
+    /// Shared with an undo in progress, which leaves the entry in place until it's done.
+    snapshot: Arc<Snapshot>,
+}
+
+impl Entry {
+    /// Whether `self` and `other` touch the same entries, or entries below one another.
+    fn overlaps(&self, other: &Entry) -> Option<PathBuf> {
+        let paths = |entry: &Entry| -> Vec<PathBuf> {
+            let mut paths = entry.snapshot.paths.clone();
+            for step in &entry.snapshot.steps {
+                paths.extend(step.paths().into_iter().map(Path::to_path_buf));
+            }
+            paths
+        };
+        let others = paths(other);
+        paths(self).into_iter().find(|path| {
+            others
+                .iter()
+                .any(|other| path.starts_with(other) || other.starts_with(path))
+        })
+    }
+
+    fn report(&self) -> HistoryEntry {
+        HistoryEntry {
+            id: self.id,
+            tool: self.tool,
+            time: self.time,
+            paths: self.snapshot.paths.clone(),
+            bytes: self.snapshot.bytes,
+            undoable: self.snapshot.skipped.is_none(),
+            warning: self
+                .snapshot
+                .skipped
+                .as_ref()
+                .map(|reason| format!("the change can't be undone: {reason}")),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    entries: VecDeque<Entry>,
+    next_id: u64,
+    bytes: u64,
+}
+
+/// The journal of the changes of a session.
+#[derive(Debug)]
+pub struct Journal {
+    config: JournalConfig,
+    state: Mutex<State>,
+}
+
+impl Journal {
+    pub fn new(config: JournalConfig) -> Self {
+        Journal {
+            config,
+            state: Mutex::new(State {
+                next_id: 1,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Snapshot the paths a change is about to touch.
+    pub async fn snapshot(&self, targets: Vec<Target>) -> Snapshot {
+        let config = self.config.clone();
+        tokio::task::spawn_blocking(move || Snapshot::capture(&config, targets))
+            .await
+            .unwrap_or_else(|e| Snapshot {
+                paths: Vec::new(),
+                steps: Vec::new(),
+                bytes: 0,
+                skipped: Some(e.to_string()),
+            })
+    }
+
+    /// Journal a change made by `tool`, forgetting the oldest changes beyond the limits.
+    pub fn record(&self, tool: &'static str, snapshot: Snapshot) {
+        if let Some(reason) = &snapshot.skipped {
+            tracing::warn!(tool, %reason, "the change can't be undone");
+        }
+        let mut state = self.state.lock().expect("poisoned");
+        let id = state.next_id;
+        state.next_id += 1;
+        state.bytes += snapshot.bytes;
+        state.entries.push_back(Entry {
+            id,
+            tool,
+            time: Utc::now(),
+            snapshot: Arc::new(snapshot),
+        });
+        while state.entries.len() > self.config.max_entries || state.bytes > self.config.max_bytes {
+            let Some(forgotten) = state.entries.pop_front() else {
+                break;
+            };
+            state.bytes -= forgotten.snapshot.bytes;
+        }
+    }
+
+    /// The changes journaled, the latest first.
+    pub fn history(&self) -> Vec<HistoryEntry> {
+        let state = self.state.lock().expect("poisoned");
+        state.entries.iter().rev().map(Entry::report).collect()
+    }
+
+    /// The changes `selection` undoes, the latest first.
+    fn select(&self, selection: Selection) -> Result<Vec<HistoryEntry>, String> {
+        let state = self.state.lock().expect("poisoned");
+        let selected: Vec<&Entry> = match selection {
+            Selection::Last(count) => state.entries.iter().rev().take(count).collect(),
+            Selection::Id(id) => {
+                let Some(position) = state.entries.iter().position(|entry| entry.id == id) else {
+                    return Err(format!("there is no change {id} in the journal"));
+                };
+                let entry = &state.entries[position];
+                for later in state.entries.range(position + 1..) {
+                    if let Some(path) = entry.overlaps(later) {
+                        return Err(format!(
+                            "{} was changed again by change {}, undo it first",
+                            path.display(),
+                            later.id
+                        ));
+                    }
+                }
+                vec![entry]
+            }
+        };
+        if selected.is_empty() {
+            return Err("there is no change to undo".to_string());
+        }
+        Ok(selected.into_iter().map(Entry::report).collect())
+    }
+
+    /// Undo the changes with `ids`, in order, until one can't be undone. Undone changes
+    /// are removed from the journal.
+    async fn undo(&self, jail: &PathJail, ids: &[u64]) -> Result<(), (usize, String)> {
+        for (undone, id) in ids.iter().enumerate() {
+            let snapshot = {
+                let state = self.state.lock().expect("poisoned");
+                let Some(entry) = state.entries.iter().find(|entry| entry.id == *id) else {
+                    return Err((undone, format!("change {id} is no longer in the journal")));
+                };
+                if let Some(reason) = &entry.snapshot.skipped {
+                    return Err((undone, format!("change {id} can't be undone: {reason}")));
+                }
+                entry.snapshot.clone()
+            };
+            let jail = jail.clone();
+            let reverted = Arc::clone(&snapshot);
+            tokio::task::spawn_blocking(move || revert(&jail, &reverted.steps))
+                .await
+                .map_err(|e| (undone, e.to_string()))?
+                .map_err(|e| (undone, format!("undoing change {id} failed: {e}")))?;
+            // only forgotten once undone, so that a failed undo can be tried again
+            let mut state = self.state.lock().expect("poisoned");
+            if let Some(position) = state.entries.iter().position(|entry| entry.id == *id) {
+                state.entries.remove(position);
+                state.bytes -= snapshot.bytes;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A change in the journal, as `fs.history` lists it.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryEntry {
+    pub id: u64,
+    pub tool: &'static str,
+    pub time: DateTime<Utc>,
+    /// Paths the change touched.
+    pub paths: Vec<PathBuf>,
+    /// Bytes of file content kept to undo the change.
+    pub bytes: u64,
+    pub undoable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+enum Selection {
+    Last(usize),
+    Id(u64),
+}
+
+/// Parameters for listing the changes of the session.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct HistoryParams {
+    /// Maximum number of changes listed, the latest first.
+    pub limit: Option<usize>,
+}
+
+/// Parameters for undoing changes of the session.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct UndoParams {
+    /// Number of changes to undo, the latest first, 1 by default.
+    pub count: Option<usize>,
+    /// Id of a change to undo, as `fs.history` lists it, instead of the latest ones.
+    pub id: Option<u64>,
+}
+
+pub struct FsHistory {
+    pub(super) ctx: Arc<FsContext>,
+}
+
+impl TypedToolHandler for FsHistory {
+    type Params = HistoryParams;
+    fn name(&self) -> &'static str {
+        "fs.history"
+    }
+
+    fn description(&self) -> &'static str {
+        embed!("tools/fs/history")
+    }
+
+    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        let mut history = self.ctx.journal.history();
+        history.truncate(params.limit.unwrap_or(usize::MAX));
+        Ok(vec![Content::text(
+            json!({ "changes": history }).to_string(),
+        )])
+    }
+}
+
+pub struct FsUndo {
+    pub(super) ctx: Arc<FsContext>,
+}
+
+impl TypedToolHandler for FsUndo {
+    type Params = UndoParams;
+    fn name(&self) -> &'static str {
+        "fs.undo"
+    }
+
+    fn description(&self) -> &'static str {
+        embed!("tools/fs/undo")
+    }
+
+    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        let selection = match (params.count, params.id) {
+            (None, Some(id)) => Selection::Id(id),
+            (count, None) => Selection::Last(count.unwrap_or(1)),
+            (Some(_), Some(_)) => {
+                return Err(mcp_core::ToolError::validation("set either count or id"));
+            }
+        };
+        let selected = self
+            .ctx
+            .journal
+            .select(selection)
+            .map_err(mcp_core::ToolError::validation)?;
+        if self.ctx.mode.guard(self.name())? {
+            return Ok(mode::dry_run(self.name(), json!({ "changes": selected })));
+        }
+        let ids: Vec<u64> = selected.iter().map(|entry| entry.id).collect();
+        if let Err((undone, error)) = self.ctx.journal.undo(&self.ctx.jail, &ids).await {
+            let error = match undone {
+                0 => error,
+                _ => format!("{error}, changes {:?} were undone before", &ids[..undone]),
+            };
+            return Err(mcp_core::ToolError::ExecutionError(error));
+        }
+        Ok(vec![Content::text(
+            json!({ "undone": selected }).to_string(),
+        )])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::testing::Scratch;
+    use super::*;
+
+    fn journal(max_entries: usize, max_bytes: u64, max_file_bytes: u64) -> Journal {
+        Journal::new(JournalConfig {
+            max_entries,
+            max_bytes,
+            max_file_bytes,
+        })
+    }
+
+    /// Journal a change to the file at `path` made by `change`.
+    async fn change(journal: &Journal, path: &Path, change: impl FnOnce()) {
+        let snapshot = journal.snapshot(vec![Target::Entry(path.into())]).await;
+        change();
+        journal.record("test", snapshot);
+    }
+
+    #[tokio::test]
+    async fn undo_restores_content_mode_and_mtime() {
+        let dir = Scratch::new("journal-undo");
+        let path = dir.write("file", "before");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        fs::File::open(&path)
+            .unwrap()
+            .set_times(FileTimes::new().set_modified(modified))
+            .unwrap();
+        let journal = journal(10, 1000, 1000);
+        change(&journal, &path, || {
+            fs::write(&path, "after").unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o777)).unwrap();
+        })
+        .await;
+        // a file created by a change is removed again
+        let created = dir.join("new/file");
+        change(&journal, &created, || {
+            fs::create_dir(dir.join("new")).unwrap();
+            fs::write(&created, "").unwrap();
+        })
+        .await;
+
+        let ids: Vec<u64> = journal.history().iter().map(|entry| entry.id).collect();
+        journal.undo(&PathJail::default(), &ids).await.unwrap();
+        assert_eq!(dir.read("file"), "before");
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.mode() & 0o777, 0o640);
+        assert_eq!(metadata.modified().unwrap(), modified);
+        assert!(!dir.join("new").exists());
+        assert!(journal.history().is_empty());
+    }
+
+    #[tokio::test]
+    async fn journals_changes_of_large_files_as_not_undoable() {
+        let dir = Scratch::new("journal-large");
+        let path = dir.write("file", "1234");
+        let journal = journal(10, 1000, 3);
+        change(&journal, &path, || fs::write(&path, "").unwrap()).await;
+
+        let history = journal.history();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].paths, [path]);
+        assert!(!history[0].undoable);
+        assert!(
+            history[0]
+                .warning
+                .as_ref()
+                .unwrap()
+                .contains("larger than 3 bytes")
+        );
+        let (undone, error) = journal
+            .undo(&PathJail::default(), &[history[0].id])
+            .await
+            .unwrap_err();
+        assert_eq!(undone, 0);
+        assert!(error.contains("can't be undone"), "{error}");
+        assert_eq!(dir.read("file"), "");
+    }
+
+    #[tokio::test]
+    async fn forgets_the_oldest_changes_beyond_the_limits() {
+        let dir = Scratch::new("journal-limits");
+        let path = dir.write("file", "123");
+        let by_entries = journal(2, 1000, 1000);
+        for _ in 0..3 {
+            change(&by_entries, &path, || {}).await;
+        }
+        let ids: Vec<u64> = by_entries.history().iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, [3, 2]);
+
+        // each change keeps the 3 bytes of the file
+        let by_bytes = journal(10, 5, 1000);
+        change(&by_bytes, &path, || {}).await;
+        change(&by_bytes, &path, || {}).await;
+        let history = by_bytes.history();
+        assert_eq!(history.len(), 1);
+        assert_eq!((history[0].id, history[0].bytes), (2, 3));
+        assert_eq!(by_bytes.state.lock().unwrap().bytes, 3);
+    }
+}
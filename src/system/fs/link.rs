@@ -0,0 +1,313 @@
+//! Creating and inspecting symbolic and hard links.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use mcp_core::{Content, handler::TypedToolHandler};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{FsContext, atomic, jail, journal::Target};
+use crate::{embed, system::mode};
+
+/// Parameters for creating a symbolic link.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct SymlinkParams {
+    /// Where the link points to, stored as is, a relative target is relative to the link's
+    /// directory.
+    pub target: String,
+    /// Path of the link to create.
+    pub path: String,
+    /// Replace an existing file or link at `path`, directories are never replaced.
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+/// Parameters for creating a hard link.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct HardlinkParams {
+    /// Path of the existing file.
+    pub source_path: String,
+    /// Path of the new link to it.
+    pub path: String,
+    /// Replace an existing file or link at `path`, directories are never replaced.
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+/// Parameters for reading a symbolic link.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ReadLinkParams {
+    /// Path of the link.
+    pub path: String,
+}
+
+pub struct FsSymlink {
+    pub(super) ctx: Arc<FsContext>,
+}
+
+impl TypedToolHandler for FsSymlink {
+    type Params = SymlinkParams;
+    fn name(&self) -> &'static str {
+        "fs.symlink"
+    }
+
+    fn description(&self) -> &'static str {
+        embed!("tools/fs/symlink")
+    }
+
+    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        let path = self.ctx.entry(&params.path).await?;
+        // the link itself is stored as is, but it must not lead out of the jail
+        let directory = path.parent().unwrap_or(Path::new("/"));
+        self.ctx
+            .path(&directory.join(&params.target).to_string_lossy())
+            .await?;
+        let replaces = check_replace(&path, params.overwrite).await?;
+        if self.ctx.mode.guard(self.name())? {
+            return Ok(mode::dry_run(
+                self.name(),
+                json!({
+                    "path": params.path,
+                    "target": params.target,
+                    "replaces": replaces,
+                }),
+            ));
+        }
+        let target = PathBuf::from(&params.target);
+        let snapshot = self
+            .ctx
+            .journal
+            .snapshot(vec![Target::Entry(path.clone())])
+            .await;
+        replace_with(&path, replaces, async |link| {
+            tokio::fs::symlink(&target, link).await
+        })
+        .await
+        .map_err(mcp_core::ToolError::execution)?;
+        self.ctx.journal.record(self.name(), snapshot);
+        Ok(vec![Content::text(
+            json!({ "replaced": replaces }).to_string(),
+        )])
+    }
+}
+
+pub struct FsHardlink {
+    pub(super) ctx: Arc<FsContext>,
+}
+
+impl TypedToolHandler for FsHardlink {
+    type Params = HardlinkParams;
+    fn name(&self) -> &'static str {
+        "fs.hardlink"
+    }
+
+    fn description(&self) -> &'static str {
+        embed!("tools/fs/hardlink")
+    }
+
+    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        let source = self.ctx.entry(&params.source_path).await?;
+        let path = self.ctx.entry(&params.path).await?;
+        let metadata = tokio::fs::symlink_metadata(&source)
+            .await
+            .map_err(mcp_core::ToolError::execution)?;
+        if metadata.is_dir() {
+            return Err(mcp_core::ToolError::validation(format!(
+                "{} is a directory, directories can't be hard linked",
+                params.source_path
+            )));
+        }
+        let replaces = check_replace(&path, params.overwrite).await?;
+        if self.ctx.mode.guard(self.name())? {
+            return Ok(mode::dry_run(
+                self.name(),
+                json!({
+                    "source_path": params.source_path,
+                    "path": params.path,
+                    "replaces": replaces,
+                }),
+            ));
+        }
+        let snapshot = self
+            .ctx
+            .journal
+            .snapshot(vec![Target::Entry(path.clone())])
+            .await;
+        replace_with(&path, replaces, async |link| {
+            tokio::fs::hard_link(&source, link).await
+        })
+        .await
+        .map_err(mcp_core::ToolError::execution)?;
+        self.ctx.journal.record(self.name(), snapshot);
+        Ok(vec![Content::text(
+            json!({ "replaced": replaces }).to_string(),
+        )])
+    }
+}
+
+pub struct FsReadLink {
+    pub(super) ctx: Arc<FsContext>,
+}
+
+impl TypedToolHandler for FsReadLink {
+    type Params = ReadLinkParams;
+    fn name(&self) -> &'static str {
+        "fs.read_link"
+    }
+
+    fn description(&self) -> &'static str {
+        embed!("tools/fs/read_link")
+    }
+
+    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        let path = self.ctx.entry(&params.path).await?;
+        let target = tokio::fs::read_link(&path)
+            .await
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::InvalidInput => {
+                    mcp_core::ToolError::validation(format!("{} is not a symlink", params.path))
+                }
+                _ => mcp_core::ToolError::execution(e),
+            })?;
+        // where the link finally leads, unless that is out of the jail
+        let jail = self.ctx.jail.clone();
+        let link = path.clone();
+        let resolved = tokio::task::spawn_blocking(move || {
+            jail::resolve(&link)
+                .ok()
+                .filter(|resolved| jail.check(resolved, false).is_ok())
+        })
+        .await
+        .map_err(mcp_core::ToolError::execution)?;
+        let dangling = tokio::fs::metadata(&path)
+            .await
+            .is_err_and(|e| e.kind() == io::ErrorKind::NotFound);
+        Ok(vec![Content::text(
+            json!({
+                "path": params.path,
+                "target": target,
+                "resolved_path": resolved,
+                "dangling": dangling,
+            })
+            .to_string(),
+        )])
+    }
+}
+
+/// Whether the symlink at `path` leads to nothing, blocking on the file system.
+pub fn is_dangling(path: &Path) -> bool {
+    std::fs::metadata(path).is_err_and(|e| e.kind() == io::ErrorKind::NotFound)
+}
+
+/// Check whether a link can be created at `path`, returns whether it replaces an entry.
+async fn check_replace(path: &Path, overwrite: bool) -> mcp_core::ToolResult<bool> {
+    let Ok(metadata) = tokio::fs::symlink_metadata(path).await else {
+        return Ok(false);
+    };
+    if metadata.is_dir() {
+        return Err(mcp_core::ToolError::validation(format!(
+            "{} is a directory, it can't be replaced by a link",
+            path.display()
+        )));
+    }
+    if !overwrite {
+        return Err(mcp_core::ToolError::validation(format!(
+            "{} already exists, set overwrite to replace it",
+            path.display()
+        )));
+    }
+    Ok(true)
+}
+
+/// Create a link at `path`, replacing the existing entry by a rename so `path` never goes
+/// missing.
+async fn replace_with(
+    path: &Path,
+    replaces: bool,
+    create: impl AsyncFnOnce(&Path) -> io::Result<()>,
+) -> io::Result<()> {
+    if !replaces {
+        return create(path).await;
+    }
+    let temp = atomic::temp_path(path);
+    create(&temp).await?;
+    let result = tokio::fs::rename(&temp, path).await;
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&temp).await;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::super::{
+        Fs,
+        jail::PathJail,
+        testing::{Scratch, call_json, context},
+    };
+    use super::*;
+
+    #[tokio::test]
+    async fn refuses_symlinks_leading_out_of_the_jail() {
+        let dir = Scratch::new("link-jail");
+        dir.write("inside/file", "");
+        dir.write("outside/secret", "");
+        let tool = FsSymlink {
+            ctx: context(Fs {
+                jail: PathJail::new(&[dir.join("inside")], &[]).unwrap(),
+                ..Fs::default()
+            }),
+        };
+        for target in [dir.param("outside/secret"), "../outside/secret".into()] {
+            let error = call_json(
+                &tool,
+                json!({"target": target, "path": dir.param("inside/link")}),
+            )
+            .await
+            .unwrap_err();
+            assert!(
+                error.to_string().contains("outside of the allowed roots"),
+                "{error}"
+            );
+            assert!(std::fs::symlink_metadata(dir.join("inside/link")).is_err());
+        }
+
+        call_json(
+            &tool,
+            json!({"target": "file", "path": dir.param("inside/link")}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            std::fs::read_link(dir.join("inside/link")).unwrap(),
+            Path::new("file")
+        );
+    }
+
+    #[tokio::test]
+    async fn refuses_to_hard_link_directories() {
+        let dir = Scratch::new("link-dir");
+        std::fs::create_dir(dir.join("dir")).unwrap();
+        let tool = FsHardlink {
+            ctx: context(Fs::default()),
+        };
+        let error = call_json(
+            &tool,
+            json!({"source_path": dir.param("dir"), "path": dir.param("link")}),
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            error.to_string().contains("can't be hard linked"),
+            "{error}"
+        );
+        assert!(std::fs::symlink_metadata(dir.join("link")).is_err());
+    }
+}
@@ -0,0 +1,258 @@
+//! Structured listing of directory trees.
+
+use std::{
+    cmp::Ordering,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use mcp_core::{Content, handler::TypedToolHandler};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    FsContext, link,
+    walk::{self, EntryType, WalkOptions},
+};
+use crate::embed;
+
+/// Entries returned per page unless the request says otherwise.
+pub const DEFAULT_LIMIT: usize = 1000;
+
+/// Parameters for listing the contents of a directory.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ListDirectoryParams {
+    /// Path to the directory to be listed.
+    pub path: String,
+    /// How many levels to list, 1 lists the entries of the directory itself.
+    pub depth: Option<usize>,
+    /// How the entries of each directory are sorted, by name by default.
+    pub sort: Option<ListSort>,
+    /// Sort in descending order.
+    #[serde(default)]
+    pub reverse: bool,
+    /// Leave out hidden files and directories.
+    #[serde(default)]
+    pub hide_hidden: bool,
+    /// Leave out the files ignored by `.gitignore`, `.ignore` and git's exclude files.
+    #[serde(default)]
+    pub respect_gitignore: bool,
+    /// Globs of the entries to leave out, relative to `path`.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Maximum number of entries returned, 1000 by default.
+    pub limit: Option<usize>,
+    /// `next_cursor` of the previous page, to continue a listing.
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ListSort {
+    #[default]
+    Name,
+    Size,
+    Modified,
+    /// Directories first, then by name.
+    Type,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListEntry {
+    pub name: String,
+    /// Path relative to the listed directory.
+    pub path: PathBuf,
+    #[serde(rename = "type")]
+    pub file_type: EntryType,
+    pub size: u64,
+    /// Permission bits, in octal.
+    pub mode: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<DateTime<Utc>>,
+    /// Where a symlink points to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<PathBuf>,
+    /// Whether a symlink leads to nothing.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub dangling: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListOutput {
+    /// Entries in depth-first order, each directory followed by its own entries.
+    pub entries: Vec<ListEntry>,
+    /// Cursor to get the next page with, when there are more entries.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+pub struct FsListDirectory {
+    pub(super) ctx: Arc<FsContext>,
+}
+
+impl TypedToolHandler for FsListDirectory {
+    type Params = ListDirectoryParams;
+    fn name(&self) -> &'static str {
+        "fs.list_directory"
+    }
+
+    fn description(&self) -> &'static str {
+        embed!("tools/fs/list")
+    }
+
+    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        let root = self.ctx.path(&params.path).await?;
+        let offset = match &params.cursor {
+            Some(cursor) => cursor
+                .parse::<usize>()
+                .map_err(|_| mcp_core::ToolError::validation("invalid cursor"))?,
+            None => 0,
+        };
+        let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+        let options = WalkOptions {
+            exclude: params.exclude.clone(),
+            hidden: !params.hide_hidden,
+            no_ignore: !params.respect_gitignore,
+            max_depth: Some(params.depth.unwrap_or(1)),
+        };
+        let walk = walk::builder(&self.ctx.jail, &root, &[], &options)
+            .map_err(mcp_core::ToolError::validation)?
+            .sort_by_file_path(compare(params.sort.unwrap_or_default(), params.reverse))
+            .build();
+        // the walk reads one directory at a time, and stops once the page is full
+        let mut entries = tokio::task::spawn_blocking(move || {
+            walk.flatten()
+                .filter(|entry| entry.depth() > 0)
+                .filter_map(|entry| list_entry(&root, &entry))
+                .skip(offset)
+                .take(limit.saturating_add(1))
+                .collect::<Vec<_>>()
+        })
+        .await
+        .map_err(mcp_core::ToolError::execution)?;
+
+        let has_more = entries.len() > limit;
+        entries.truncate(limit);
+        let output = ListOutput {
+            next_cursor: has_more.then(|| (offset + entries.len()).to_string()),
+            entries,
+        };
+        Ok(vec![Content::text(
+            serde_json::to_string(&output).expect("invalid json"),
+        )])
+    }
+}
+
+fn list_entry(root: &Path, entry: &ignore::DirEntry) -> Option<ListEntry> {
+    let metadata = entry.metadata().ok()?;
+    let file_type = EntryType::from(metadata.file_type());
+    Some(ListEntry {
+        name: entry.file_name().to_string_lossy().into_owned(),
+        path: entry.path().strip_prefix(root).ok()?.to_path_buf(),
+        file_type,
+        size: metadata.len(),
+        mode: format!("{:o}", metadata.permissions().mode() & 0o7777),
+        modified: metadata.modified().ok().map(DateTime::from),
+        target: (file_type == EntryType::Symlink)
+            .then(|| std::fs::read_link(entry.path()).ok())
+            .flatten(),
+        dangling: file_type == EntryType::Symlink && link::is_dangling(entry.path()),
+    })
+}
+
+/// How the entries of each directory are sorted, by `sort` and then by name.
+fn compare(
+    sort: ListSort,
+    reverse: bool,
+) -> impl Fn(&Path, &Path) -> Ordering + Send + Sync + 'static {
+    move |a, b| {
+        let metadata = |path: &Path| std::fs::symlink_metadata(path).ok();
+        let ordering = match sort {
+            ListSort::Name => Ordering::Equal,
+            ListSort::Size => {
+                let size = |path| metadata(path).map(|metadata| metadata.len());
+                size(a).cmp(&size(b))
+            }
+            ListSort::Modified => {
+                let modified = |path| metadata(path).and_then(|metadata| metadata.modified().ok());
+                modified(a).cmp(&modified(b))
+            }
+            ListSort::Type => {
+                let not_dir = |path| !metadata(path).is_some_and(|metadata| metadata.is_dir());
+                not_dir(a).cmp(&not_dir(b))
+            }
+        }
+        .then_with(|| a.file_name().cmp(&b.file_name()));
+        if reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::super::{
+        Fs,
+        testing::{Scratch, call_json, context},
+    };
+    use super::*;
+
+    async fn list(params: Value) -> Vec<String> {
+        let tool = FsListDirectory {
+            ctx: context(Fs::default()),
+        };
+        let output = call_json(&tool, params).await.unwrap();
+        output["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["path"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn lists_everything_by_default() {
+        let dir = Scratch::new("list-default");
+        dir.write(".gitignore", "ignored\n");
+        dir.write(".hidden", "");
+        dir.write("ignored", "");
+        dir.write("visible", "");
+        assert_eq!(
+            list(json!({"path": dir.param("")})).await,
+            [".gitignore", ".hidden", "ignored", "visible"]
+        );
+        assert_eq!(
+            list(json!({"path": dir.param(""), "hide_hidden": true})).await,
+            ["ignored", "visible"]
+        );
+        assert_eq!(
+            list(json!({"path": dir.param(""), "respect_gitignore": true})).await,
+            [".gitignore", ".hidden", "visible"]
+        );
+    }
+
+    #[tokio::test]
+    async fn lists_depth_first_page_by_page() {
+        let dir = Scratch::new("list-pages");
+        dir.write("a/b", "");
+        dir.write("c", "");
+        let tool = FsListDirectory {
+            ctx: context(Fs::default()),
+        };
+        let params = json!({"path": dir.param(""), "depth": 2, "limit": 2});
+        let first = call_json(&tool, params.clone()).await.unwrap();
+        assert_eq!(first["entries"][0]["path"], "a");
+        assert_eq!(first["entries"][1]["path"], "a/b");
+        let mut params = params;
+        params["cursor"] = first["next_cursor"].clone();
+        let second = call_json(&tool, params).await.unwrap();
+        assert_eq!(second["entries"][0]["path"], "c");
+        assert!(second.get("next_cursor").is_none());
+    }
+}
@@ -0,0 +1,858 @@
+//! Application of unified diffs spanning several files.
+//!
+//! Every hunk is located first, allowing it to have moved (offset) and ignoring some of
+//! its context lines when it doesn't match exactly (fuzz), as `patch` does. Files are
+//! only touched once every hunk of every file applies, and the changes already made are
+//! rolled back when a later one fails.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use mcp_core::{Content, handler::TypedToolHandler};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    FsContext,
+    transaction::{self, Change, commit},
+};
+use crate::{embed, system::mode};
+
+/// Context lines which may be ignored at each end of a hunk by default, as `patch`.
+pub const DEFAULT_FUZZ: usize = 2;
+
+/// Parameters for applying a patch.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ApplyPatchParams {
+    /// Unified diff to apply, it may change, create, delete and rename several files.
+    pub patch: String,
+    /// Directory relative paths of the patch are resolved against, the first root the
+    /// server is confined to by default, and required when it isn't confined.
+    pub directory: Option<String>,
+    /// Number of leading components stripped from the paths of the patch, as `patch -p`.
+    /// Git style `a/` and `b/` prefixes are stripped by default.
+    pub strip: Option<usize>,
+    /// Maximum number of context lines ignored at each end of a hunk which doesn't match
+    /// exactly, 2 by default.
+    pub fuzz: Option<usize>,
+    /// Only report whether the patch applies, without changing any file.
+    #[serde(default)]
+    pub check: bool,
+}
+
+/// What applying a patch did, or would do, to each file.
+#[derive(Debug, Serialize)]
+pub struct PatchReport {
+    /// Whether every hunk applies, nothing is changed otherwise.
+    pub applies: bool,
+    pub files: Vec<FileReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileReport {
+    pub path: String,
+    pub action: FileAction,
+    /// Previous path of a renamed file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub hunks: Vec<HunkReport>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileAction {
+    Modify,
+    Create,
+    Delete,
+    Rename,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HunkReport {
+    /// Number of the hunk in the file, starting at 1.
+    pub hunk: usize,
+    pub applied: bool,
+    /// Lines between where the hunk says it applies and where it does.
+    pub offset: isize,
+    /// Context lines ignored at each end of the hunk.
+    pub fuzz: usize,
+}
+
+pub struct FsApplyPatch {
+    pub(super) ctx: Arc<FsContext>,
+}
+
+impl TypedToolHandler for FsApplyPatch {
+    type Params = ApplyPatchParams;
+    fn name(&self) -> &'static str {
+        "fs.apply_patch"
+    }
+
+    fn description(&self) -> &'static str {
+        embed!("tools/fs/apply_patch")
+    }
+
+    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        let file_patches = parse(&params.patch).map_err(mcp_core::ToolError::validation)?;
+        if file_patches.is_empty() {
+            return Err(mcp_core::ToolError::validation(
+                "the patch doesn't change any file",
+            ));
+        }
+        let directory = match (&params.directory, self.ctx.jail.roots().first()) {
+            (Some(directory), _) => PathBuf::from(directory),
+            (None, Some(root)) => root.clone(),
+            (None, None) => {
+                return Err(mcp_core::ToolError::validation(
+                    "directory is required, the server isn't confined to any root",
+                ));
+            }
+        };
+        let strip = params.strip.unwrap_or_else(|| default_strip(&file_patches));
+        let fuzz = params.fuzz.unwrap_or(DEFAULT_FUZZ);
+
+        let mut report = PatchReport {
+            applies: true,
+            files: Vec::new(),
+        };
+        let mut changes = Vec::new();
+        for file_patch in &file_patches {
+            let (file_report, change) = self.prepare(file_patch, &directory, strip, fuzz).await;
+            report.applies &= file_report.error.is_none();
+            report.files.push(file_report);
+            changes.extend(change);
+        }
+        let report_json = serde_json::to_value(&report).expect("invalid json");
+        if params.check {
+            return Ok(vec![Content::text(report_json.to_string())]);
+        }
+        if !report.applies {
+            return Err(mcp_core::ToolError::ExecutionError(format!(
+                "the patch doesn't apply, no file was changed: {report_json}"
+            )));
+        }
+        if self.ctx.mode.guard(self.name())? {
+            return Ok(mode::dry_run(self.name(), report_json));
+        }
+        let snapshot = self
+            .ctx
+            .journal
+            .snapshot(transaction::targets(&changes))
+            .await;
+        commit(&self.ctx.jail, changes)
+            .await
+            .map_err(mcp_core::ToolError::execution)?;
+        self.ctx.journal.record(self.name(), snapshot);
+        Ok(vec![Content::text(report_json.to_string())])
+    }
+}
+
+impl FsApplyPatch {
+    /// Work out the change a file patch makes, without touching the file system.
+    async fn prepare(
+        &self,
+        file_patch: &FilePatch,
+        directory: &Path,
+        strip: usize,
+        fuzz: usize,
+    ) -> (FileReport, Option<Change>) {
+        let old = file_patch.old_path(strip);
+        let new = file_patch.new_path(strip);
+        let (action, path, from) = match (&old, &new) {
+            (None, Some(new)) => (FileAction::Create, new.clone(), None),
+            (Some(old), None) => (FileAction::Delete, old.clone(), None),
+            (Some(old), Some(new)) if old != new => {
+                (FileAction::Rename, new.clone(), Some(old.clone()))
+            }
+            (_, new) => (FileAction::Modify, new.clone().unwrap_or_default(), None),
+        };
+        let mut report = FileReport {
+            path: path.clone(),
+            action,
+            from: from.clone(),
+            error: None,
+            hunks: Vec::new(),
+        };
+        match self
+            .prepare_change(file_patch, directory, fuzz, &mut report)
+            .await
+        {
+            Ok(change) => (report, Some(change)),
+            Err(error) => {
+                report.error = Some(error);
+                (report, None)
+            }
+        }
+    }
+
+    async fn prepare_change(
+        &self,
+        file_patch: &FilePatch,
+        directory: &Path,
+        fuzz: usize,
+        report: &mut FileReport,
+    ) -> Result<Change, String> {
+        let (action, path, from) = (report.action, report.path.as_str(), report.from.as_deref());
+        let resolve_entry = async |path: &str| {
+            self.ctx
+                .entry(&directory.join(path).to_string_lossy())
+                .await
+                .map_err(|e| e.to_string())
+        };
+        let target = match action {
+            FileAction::Delete | FileAction::Rename => resolve_entry(path).await?,
+            FileAction::Create | FileAction::Modify => self
+                .ctx
+                .path(&directory.join(path).to_string_lossy())
+                .await
+                .map_err(|e| e.to_string())?,
+        };
+        let source = match from {
+            Some(from) => resolve_entry(from).await?,
+            None => target.clone(),
+        };
+        let exists = |path: &Path| std::fs::symlink_metadata(path).is_ok();
+        let original = match action {
+            FileAction::Create => {
+                if exists(&target) {
+                    return Err(format!("{path} already exists"));
+                }
+                None
+            }
+            _ => Some(
+                tokio::fs::read(&source)
+                    .await
+                    .map_err(|e| format!("{}: {e}", source.display()))?,
+            ),
+        };
+        if action == FileAction::Rename && exists(&target) {
+            return Err(format!("{path} already exists"));
+        }
+        let original_text = match &original {
+            Some(content) => std::str::from_utf8(content)
+                .map_err(|_| format!("{} is not a utf-8 text file", source.display()))?,
+            None => "",
+        };
+        let mut text = PatchedText::new(original_text);
+        let mut all_applied = true;
+        for (index, hunk) in file_patch.hunks.iter().enumerate() {
+            let applied = text.apply(hunk, fuzz);
+            all_applied &= applied.is_some();
+            let (offset, fuzz) = applied.unwrap_or_default();
+            report.hunks.push(HunkReport {
+                hunk: index + 1,
+                applied: applied.is_some(),
+                offset,
+                fuzz,
+            });
+        }
+        if !all_applied {
+            return Err("some hunks don't apply".to_string());
+        }
+        let content = text.into_string();
+        Ok(match action {
+            FileAction::Delete => {
+                if !content.is_empty() {
+                    return Err(format!(
+                        "{path} isn't empty after removing the lines of the patch"
+                    ));
+                }
+                Change::Delete { path: target }
+            }
+            FileAction::Rename => Change::Rename {
+                from: source,
+                to: target,
+                content: (content.as_bytes() != original_text.as_bytes())
+                    .then(|| content.into_bytes()),
+            },
+            FileAction::Create | FileAction::Modify => Change::Write {
+                path: target,
+                content: content.into_bytes(),
+                original,
+            },
+        })
+    }
+}
+
+/// The changes a patch makes to one file.
+#[derive(Debug, Default)]
+struct FilePatch {
+    /// Paths of the `---` and `+++` lines, or of the `diff --git` line, `None` for `/dev/null`.
+    old: Option<String>,
+    new: Option<String>,
+    /// Paths of git's `rename from` and `rename to` lines, which have no prefix.
+    rename_from: Option<String>,
+    rename_to: Option<String>,
+    hunks: Vec<Hunk>,
+}
+
+impl FilePatch {
+    fn old_path(&self, strip: usize) -> Option<String> {
+        match &self.rename_from {
+            Some(path) => Some(path.clone()),
+            None => self.old.as_deref().map(|path| strip_path(path, strip)),
+        }
+    }
+
+    fn new_path(&self, strip: usize) -> Option<String> {
+        match &self.rename_to {
+            Some(path) => Some(path.clone()),
+            None => self.new.as_deref().map(|path| strip_path(path, strip)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Hunk {
+    old_start: usize,
+    old_count: usize,
+    lines: Vec<HunkLine>,
+    /// The last line on the old side has no newline.
+    old_no_newline: bool,
+    /// The last line on the new side has no newline.
+    new_no_newline: bool,
+}
+
+impl Hunk {
+    /// Handle a `\ No newline at end of file` marker, which follows the line it is about.
+    fn mark_no_newline(&mut self) {
+        match self.lines.last() {
+            Some(HunkLine::Remove(_)) => self.old_no_newline = true,
+            Some(HunkLine::Add(_)) => self.new_no_newline = true,
+            Some(HunkLine::Context(_)) => {
+                self.old_no_newline = true;
+                self.new_no_newline = true;
+            }
+            None => {}
+        }
+    }
+}
+
+#[derive(Debug)]
+enum HunkLine {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+/// Strip the git style `a/` and `b/` prefixes when every path has them.
+fn default_strip(file_patches: &[FilePatch]) -> usize {
+    let prefixed = file_patches.iter().all(|file_patch| {
+        file_patch
+            .old
+            .as_deref()
+            .is_none_or(|path| path.starts_with("a/"))
+            && file_patch
+                .new
+                .as_deref()
+                .is_none_or(|path| path.starts_with("b/"))
+    });
+    usize::from(prefixed)
+}
+
+fn strip_path(path: &str, strip: usize) -> String {
+    path.splitn(strip + 1, '/')
+        .last()
+        .unwrap_or(path)
+        .to_string()
+}
+
+/// The path of a `---` or `+++` line, without the timestamp some tools add.
+fn header_path(path: &str) -> Option<String> {
+    let path = path.split('\t').next().unwrap_or(path).trim_end();
+    (path != "/dev/null").then(|| path.to_string())
+}
+
+fn parse(patch: &str) -> Result<Vec<FilePatch>, String> {
+    let mut file_patches: Vec<FilePatch> = Vec::new();
+    let mut lines = patch.lines().peekable();
+    // whether the current file patch already has its `---` line
+    let mut has_old_header = false;
+    while let Some(line) = lines.next() {
+        if let Some(paths) = line.strip_prefix("diff --git ") {
+            let (old, new) = paths
+                .split_once(" b/")
+                .map(|(old, new)| (old.to_string(), format!("b/{new}")))
+                .unwrap_or_default();
+            file_patches.push(FilePatch {
+                old: Some(old),
+                new: Some(new),
+                ..Default::default()
+            });
+            has_old_header = false;
+        } else if line.starts_with("GIT binary patch") || line.starts_with("Binary files ") {
+            return Err("binary patches are not supported".to_string());
+        } else if let Some(path) = line.strip_prefix("rename from ") {
+            current(&mut file_patches).rename_from = Some(path.to_string());
+        } else if let Some(path) = line.strip_prefix("rename to ") {
+            current(&mut file_patches).rename_to = Some(path.to_string());
+        } else if line.starts_with("new file mode") {
+            current(&mut file_patches).old = None;
+        } else if line.starts_with("deleted file mode") {
+            current(&mut file_patches).new = None;
+        } else if let Some(path) = line.strip_prefix("--- ") {
+            let starts_file = file_patches
+                .last()
+                .is_none_or(|file_patch| has_old_header || !file_patch.hunks.is_empty());
+            if starts_file {
+                file_patches.push(FilePatch::default());
+            }
+            current(&mut file_patches).old = header_path(path);
+            has_old_header = true;
+        } else if let Some(path) = line.strip_prefix("+++ ") {
+            current(&mut file_patches).new = header_path(path);
+        } else if let Some(header) = line.strip_prefix("@@ ") {
+            let mut hunk =
+                parse_hunk_header(header).ok_or_else(|| format!("invalid hunk header: {line}"))?;
+            let (mut old_left, mut new_left) = (hunk.old_count, parse_new_count(header));
+            while old_left > 0 || new_left > 0 {
+                let Some(line) = lines.next() else {
+                    return Err(format!("the hunk {line} is cut short"));
+                };
+                let hunk_line = match line.split_at_checked(1) {
+                    Some(("+", text)) => HunkLine::Add(text.to_string()),
+                    Some(("-", text)) => HunkLine::Remove(text.to_string()),
+                    Some((" ", text)) => HunkLine::Context(text.to_string()),
+                    // some tools strip the space of empty context lines
+                    None => HunkLine::Context(String::new()),
+                    Some(("\\", _)) => {
+                        hunk.mark_no_newline();
+                        continue;
+                    }
+                    _ => return Err(format!("invalid line in hunk: {line}")),
+                };
+                match &hunk_line {
+                    HunkLine::Add(_) => new_left = new_left.saturating_sub(1),
+                    HunkLine::Remove(_) => old_left = old_left.saturating_sub(1),
+                    HunkLine::Context(_) => {
+                        old_left = old_left.saturating_sub(1);
+                        new_left = new_left.saturating_sub(1);
+                    }
+                }
+                hunk.lines.push(hunk_line);
+            }
+            if lines.next_if(|line| line.starts_with('\\')).is_some() {
+                hunk.mark_no_newline();
+            }
+            current(&mut file_patches).hunks.push(hunk);
+        }
+    }
+    Ok(file_patches)
+}
+
+fn current(file_patches: &mut Vec<FilePatch>) -> &mut FilePatch {
+    if file_patches.is_empty() {
+        file_patches.push(FilePatch::default());
+    }
+    file_patches.last_mut().expect("just pushed")
+}
+
+/// Parse `-l,s +l,s @@`, the old side of a hunk header.
+fn parse_hunk_header(header: &str) -> Option<Hunk> {
+    let mut ranges = header.split_whitespace();
+    let (old_start, old_count) = parse_range(ranges.next()?.strip_prefix('-')?)?;
+    Some(Hunk {
+        old_start,
+        old_count,
+        ..Default::default()
+    })
+}
+
+fn parse_new_count(header: &str) -> usize {
+    header
+        .split_whitespace()
+        .nth(1)
+        .and_then(|range| range.strip_prefix('+'))
+        .and_then(parse_range)
+        .map_or(0, |(_, count)| count)
+}
+
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    match range.split_once(',') {
+        Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+        None => Some((range.parse().ok()?, 1)),
+    }
+}
+
+/// The lines of a file as hunks are applied to it.
+struct PatchedText {
+    lines: Vec<String>,
+    trailing_newline: bool,
+    crlf: bool,
+    /// Lines added minus lines removed by the hunks applied so far.
+    shift: isize,
+    /// Hunks apply in order, the next one can't start before this line.
+    min_start: usize,
+}
+
+impl PatchedText {
+    fn new(text: &str) -> Self {
+        let mut lines: Vec<String> = text.split('\n').map(ToOwned::to_owned).collect();
+        let trailing_newline = text.ends_with('\n');
+        if trailing_newline || text.is_empty() {
+            lines.pop();
+        }
+        let crlf = lines.first().is_some_and(|line| line.ends_with('\r'));
+        PatchedText {
+            lines,
+            trailing_newline: trailing_newline || text.is_empty(),
+            crlf,
+            shift: 0,
+            min_start: 0,
+        }
+    }
+
+    /// Apply a hunk, returning its offset and fuzz, or `None` when it doesn't match.
+    fn apply(&mut self, hunk: &Hunk, max_fuzz: usize) -> Option<(isize, usize)> {
+        let leading = hunk
+            .lines
+            .iter()
+            .take_while(|line| matches!(line, HunkLine::Context(_)))
+            .count();
+        let trailing = hunk
+            .lines
+            .iter()
+            .rev()
+            .take_while(|line| matches!(line, HunkLine::Context(_)))
+            .count();
+        let expected = if hunk.old_count == 0 {
+            hunk.old_start
+        } else {
+            hunk.old_start.saturating_sub(1)
+        };
+        let expected = expected.saturating_add_signed(self.shift);
+        for fuzz in 0..=max_fuzz {
+            let (front, back) = (fuzz.min(leading), fuzz.min(trailing));
+            // once every context line is ignored, more fuzz doesn't change anything
+            if fuzz > 0 && front < fuzz && back < fuzz {
+                break;
+            }
+            let lines = &hunk.lines[front..hunk.lines.len() - back];
+            let old: Vec<&str> = lines
+                .iter()
+                .filter_map(|line| match line {
+                    HunkLine::Context(text) | HunkLine::Remove(text) => Some(text.as_str()),
+                    HunkLine::Add(_) => None,
+                })
+                .collect();
+            let Some(at) = self.find(&old, expected + front) else {
+                continue;
+            };
+            let new: Vec<String> = lines
+                .iter()
+                .filter_map(|line| match line {
+                    HunkLine::Context(text) | HunkLine::Add(text) => Some(text.clone()),
+                    HunkLine::Remove(_) => None,
+                })
+                .enumerate()
+                .map(|(idx, text)| match lines_context(lines, idx) {
+                    // keep the file's version of context lines, which may differ in whitespace
+                    Some(offset) => self.lines[at + offset].clone(),
+                    None if self.crlf && !text.ends_with('\r') => format!("{text}\r"),
+                    None => text,
+                })
+                .collect();
+            let ends_file = at + old.len() == self.lines.len();
+            let new_len = new.len();
+            self.lines.splice(at..at + old.len(), new);
+            if ends_file && hunk.new_no_newline {
+                self.trailing_newline = false;
+            } else if ends_file && hunk.old_no_newline {
+                self.trailing_newline = true;
+            }
+            self.shift += new_len as isize - old.len() as isize;
+            self.min_start = at + new_len;
+            return Some((at as isize - (expected + front) as isize, fuzz));
+        }
+        None
+    }
+
+    /// Find where `old` lines are, the closest to `expected`, exactly or ignoring trailing
+    /// whitespace.
+    fn find(&self, old: &[&str], expected: usize) -> Option<usize> {
+        let last = self.lines.len().checked_sub(old.len())?;
+        if last < self.min_start {
+            return None;
+        }
+        let expected = expected.clamp(self.min_start, last);
+        let candidates = (0..=(last - self.min_start)).flat_map(|distance| {
+            let before = expected
+                .checked_sub(distance)
+                .filter(|at| *at >= self.min_start);
+            let after = Some(expected + distance).filter(|at| *at <= last && distance > 0);
+            before.into_iter().chain(after)
+        });
+        let matches = |at: usize, same: fn(&str, &str) -> bool| {
+            old.iter()
+                .enumerate()
+                .all(|(idx, line)| same(&self.lines[at + idx], line))
+        };
+        let candidates: Vec<usize> = candidates.collect();
+        candidates
+            .iter()
+            .copied()
+            .find(|at| matches(*at, |a, b| a == b))
+            .or_else(|| {
+                candidates
+                    .iter()
+                    .copied()
+                    .find(|at| matches(*at, |a, b| a.trim_end() == b.trim_end()))
+            })
+    }
+
+    fn into_string(self) -> String {
+        let mut text = self.lines.join("\n");
+        if self.trailing_newline && !self.lines.is_empty() {
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// When the `idx`th line of the new side of `lines` is a context line, its position on
+/// the old side.
+fn lines_context(lines: &[HunkLine], idx: usize) -> Option<usize> {
+    let mut old = 0;
+    let mut new = 0;
+    for line in lines {
+        match line {
+            HunkLine::Context(_) if new == idx => return Some(old),
+            HunkLine::Context(_) => {
+                old += 1;
+                new += 1;
+            }
+            HunkLine::Add(_) if new == idx => return None,
+            HunkLine::Add(_) => new += 1,
+            HunkLine::Remove(_) => old += 1,
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::super::{
+        Fs,
+        jail::PathJail,
+        testing::{Scratch, call_json, context},
+    };
+    use super::*;
+
+    /// Apply the hunks of the first file of `patch` to `text`, with the offset and fuzz of
+    /// each hunk, `None` when one of them doesn't apply.
+    fn apply(text: &str, patch: &str, fuzz: usize) -> Option<(String, Vec<(isize, usize)>)> {
+        let file_patches = parse(patch).unwrap();
+        let mut patched = PatchedText::new(text);
+        let applied = file_patches[0]
+            .hunks
+            .iter()
+            .map(|hunk| patched.apply(hunk, fuzz))
+            .collect::<Option<Vec<_>>>()?;
+        Some((patched.into_string(), applied))
+    }
+
+    #[test]
+    fn applies_at_an_offset() {
+        let patch = "@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n";
+        assert_eq!(
+            apply("x\ny\na\nb\nc\n", patch, 0),
+            Some(("x\ny\na\nB\nc\n".to_string(), vec![(2, 0)]))
+        );
+        // the closest match wins
+        assert_eq!(
+            apply("a\nb\nc\na\nb\nc\n", "@@ -4,3 +4,3 @@\n a\n-b\n+B\n c\n", 0),
+            Some(("a\nb\nc\na\nB\nc\n".to_string(), vec![(0, 0)]))
+        );
+    }
+
+    #[test]
+    fn applies_with_fuzz() {
+        let patch = "@@ -1,4 +1,4 @@\n A\n b\n-c\n+C\n d\n";
+        assert_eq!(apply("a\nb\nc\nd\n", patch, 0), None);
+        assert_eq!(
+            apply("a\nb\nc\nd\n", patch, 2),
+            Some(("a\nb\nC\nd\n".to_string(), vec![(0, 1)]))
+        );
+    }
+
+    #[test]
+    fn ignores_trailing_whitespace_of_context_lines() {
+        assert_eq!(
+            apply("a  \nb\n", "@@ -1,2 +1,2 @@\n a\n-b\n+B\n", 0),
+            Some(("a  \nB\n".to_string(), vec![(0, 0)]))
+        );
+    }
+
+    #[test]
+    fn handles_missing_newlines_at_the_end() {
+        let remove_newline = "@@ -1,2 +1,2 @@\n a\n-b\n+b\n\\ No newline at end of file\n";
+        assert_eq!(apply("a\nb\n", remove_newline, 0).unwrap().0, "a\nb");
+        let add_newline = "@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+b\n";
+        assert_eq!(apply("a\nb", add_newline, 0).unwrap().0, "a\nb\n");
+        let keep = "@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+B\n\\ No newline at end of file\n";
+        assert_eq!(apply("a\nb", keep, 0).unwrap().0, "a\nB");
+    }
+
+    #[test]
+    fn keeps_crlf_line_endings() {
+        assert_eq!(
+            apply(
+                "a\r\nb\r\nc\r\n",
+                "@@ -1,3 +1,4 @@\n a\n-b\n+B\n+B2\n c\n",
+                0
+            )
+            .unwrap()
+            .0,
+            "a\r\nB\r\nB2\r\nc\r\n"
+        );
+    }
+
+    #[test]
+    fn creates_and_empties_files() {
+        assert_eq!(
+            apply("", "@@ -0,0 +1,2 @@\n+a\n+b\n", 0).unwrap().0,
+            "a\nb\n"
+        );
+        assert_eq!(apply("a\n", "@@ -1 +0,0 @@\n-a\n", 0).unwrap().0, "");
+    }
+
+    #[test]
+    fn rejects_hunks_which_do_not_apply() {
+        assert_eq!(apply("a\nb\n", "@@ -1,2 +1,2 @@\n a\n-x\n+y\n", 3), None);
+        // hunks apply in order, one can't go back before the previous one
+        let out_of_order = "@@ -3 +3 @@\n-c\n+C\n@@ -1 +1 @@\n-a\n+A\n";
+        assert_eq!(apply("a\nb\nc\nd\n", out_of_order, 0), None);
+    }
+
+    #[test]
+    fn parses_git_headers() {
+        let patch = "\
+diff --git a/old.txt b/new.txt
+similarity index 90%
+rename from old.txt
+rename to new.txt
+--- a/old.txt
++++ b/new.txt
+@@ -1 +1 @@
+-x
++y
+diff --git a/added.txt b/added.txt
+new file mode 100644
+index 0000000..3e75765
+--- /dev/null
++++ b/added.txt
+@@ -0,0 +1 @@
++new
+diff --git a/gone.txt b/gone.txt
+deleted file mode 100644
+index 3e75765..0000000
+--- a/gone.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-old
+diff --git a/moved.txt b/sub/moved.txt
+similarity index 100%
+rename from moved.txt
+rename to sub/moved.txt
+";
+        let file_patches = parse(patch).unwrap();
+        let strip = default_strip(&file_patches);
+        assert_eq!(strip, 1);
+        let paths: Vec<_> = file_patches
+            .iter()
+            .map(|file_patch| {
+                (
+                    file_patch.old_path(strip),
+                    file_patch.new_path(strip),
+                    file_patch.hunks.len(),
+                )
+            })
+            .collect();
+        let path = |path: &str| Some(path.to_string());
+        assert_eq!(
+            paths,
+            vec![
+                (path("old.txt"), path("new.txt"), 1),
+                (None, path("added.txt"), 1),
+                (path("gone.txt"), None, 1),
+                (path("moved.txt"), path("sub/moved.txt"), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_plain_unified_diffs() {
+        let patch = "--- src/a.rs\t2024-01-01 00:00:00\n+++ src/a.rs\t2024-01-02 00:00:00\n@@ -1 +1 @@\n-a\n+b\n--- /dev/null\n+++ src/b.rs\n@@ -0,0 +1 @@\n+b\n";
+        let file_patches = parse(patch).unwrap();
+        assert_eq!(default_strip(&file_patches), 0);
+        assert_eq!(file_patches.len(), 2);
+        assert_eq!(file_patches[0].old_path(0).as_deref(), Some("src/a.rs"));
+        assert_eq!(file_patches[1].old_path(0), None);
+        assert_eq!(file_patches[1].new_path(1).as_deref(), Some("b.rs"));
+    }
+
+    #[test]
+    fn rejects_invalid_patches() {
+        assert!(
+            parse("@@ -1,2 +1,2 @@\n a\n")
+                .unwrap_err()
+                .contains("cut short")
+        );
+        assert!(
+            parse("@@ -1 +1 @@\n?a\n")
+                .unwrap_err()
+                .contains("invalid line")
+        );
+        assert!(
+            parse("@@ -x +1 @@\n")
+                .unwrap_err()
+                .contains("invalid hunk header")
+        );
+        assert!(
+            parse("diff --git a/b b/b\nBinary files a/b and b/b differ\n")
+                .unwrap_err()
+                .contains("binary")
+        );
+    }
+
+    #[tokio::test]
+    async fn resolves_relative_paths_against_the_first_root() {
+        let dir = Scratch::new("patch-root");
+        dir.write("src/file", "a\n");
+        let patch = "--- a/src/file\n+++ b/src/file\n@@ -1 +1 @@\n-a\n+b\n";
+        let confined = FsApplyPatch {
+            ctx: context(Fs {
+                jail: PathJail::new(&[dir.to_path_buf()], &[]).unwrap(),
+                ..Fs::default()
+            }),
+        };
+        call_json(&confined, json!({"patch": patch})).await.unwrap();
+        assert_eq!(dir.read("src/file"), "b\n");
+
+        // without a root, the directory has to be given
+        let unconfined = FsApplyPatch {
+            ctx: context(Fs::default()),
+        };
+        let error = call_json(&unconfined, json!({"patch": patch}))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, mcp_core::ToolError::InvalidParameters(_)),
+            "{error}"
+        );
+        dir.write("src/file", "a\n");
+        let report = call_json(
+            &unconfined,
+            json!({"patch": patch, "directory": dir.param(""), "check": true}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(report["applies"], true);
+    }
+}
@@ -0,0 +1,249 @@
+//! Line-oriented reading of text files.
+
+use std::{
+    io::{self, BufRead, BufReader},
+    path::Path,
+    sync::Arc,
+};
+
+use mcp_core::{Content, handler::TypedToolHandler};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::FsContext;
+use crate::embed;
+
+/// Lines longer than this are truncated unless the request says otherwise.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 2000;
+
+/// Parameters for reading lines from a file.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ReadLinesParams {
+    /// Path to the file to be read.
+    pub path: String,
+    /// Number of the first line to read, starting at 1.
+    pub start_line: Option<usize>,
+    /// Number of lines to read, defaults to as many as fit in the server's maximum read size.
+    pub count: Option<usize>,
+    /// Lines longer than this many bytes are truncated, 2000 by default.
+    pub max_line_length: Option<usize>,
+}
+
+/// Which lines a `fs.read_lines` returned.
+#[derive(Debug, Serialize)]
+pub struct ReadLinesOutput {
+    /// Number of the first returned line.
+    pub start_line: usize,
+    /// Number of returned lines.
+    pub lines: usize,
+    /// Number of lines in the file.
+    pub total_lines: usize,
+    /// Number of returned lines which were truncated.
+    pub truncated_lines: usize,
+    /// Whether the file continues after the returned lines.
+    pub has_more: bool,
+    /// Line to read the rest of the file from.
+    pub next_line: usize,
+}
+
+pub struct FsReadLines {
+    pub(super) ctx: Arc<FsContext>,
+}
+
+impl TypedToolHandler for FsReadLines {
+    type Params = ReadLinesParams;
+    fn name(&self) -> &'static str {
+        "fs.read_lines"
+    }
+
+    fn description(&self) -> &'static str {
+        embed!("tools/fs/read_lines")
+    }
+
+    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        let path = self.ctx.path(&params.path).await?;
+        let window = LineWindow {
+            start_line: params.start_line.unwrap_or(1).max(1),
+            count: params.count.unwrap_or(usize::MAX),
+            max_line_length: params.max_line_length.unwrap_or(DEFAULT_MAX_LINE_LENGTH),
+            max_bytes: self.ctx.max_read_bytes as usize,
+        };
+        let (content, output) = tokio::task::spawn_blocking(move || window.read(&path))
+            .await
+            .map_err(mcp_core::ToolError::execution)?
+            .map_err(mcp_core::ToolError::execution)?;
+        Ok(vec![
+            Content::text(content),
+            Content::text(serde_json::to_string(&output).expect("invalid json")),
+        ])
+    }
+}
+
+/// The lines to return from a file.
+struct LineWindow {
+    start_line: usize,
+    count: usize,
+    max_line_length: usize,
+    /// Maximum size of the numbered lines returned.
+    max_bytes: usize,
+}
+
+impl LineWindow {
+    /// Scan the whole file to count its lines, keeping at most `max_line_length` bytes of
+    /// each line in the window, so a huge single-line file doesn't end up in memory.
+    fn read(&self, path: &Path) -> io::Result<(String, ReadLinesOutput)> {
+        let mut reader = BufReader::new(std::fs::File::open(path)?);
+        let mut collected = Collected::default();
+        let mut line_number = 1;
+        let mut line = Vec::new();
+        let mut line_length = 0;
+        loop {
+            let buf = reader.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            let newline = buf.iter().position(|&b| b == b'\n');
+            let chunk = &buf[..newline.unwrap_or(buf.len())];
+            if self.wants(line_number, &collected) {
+                let keep = self
+                    .max_line_length
+                    .saturating_sub(line.len())
+                    .min(chunk.len());
+                line.extend_from_slice(&chunk[..keep]);
+            }
+            line_length += chunk.len();
+            let consumed = chunk.len() + newline.map_or(0, |_| 1);
+            reader.consume(consumed);
+            if newline.is_some() {
+                if self.wants(line_number, &collected) {
+                    collected.push(line_number, &line, line_length, self.max_bytes);
+                }
+                line.clear();
+                line_length = 0;
+                line_number += 1;
+            }
+        }
+        let mut total_lines = line_number - 1;
+        // the last line doesn't end with a newline
+        if line_length > 0 {
+            total_lines += 1;
+            if self.wants(line_number, &collected) {
+                collected.push(line_number, &line, line_length, self.max_bytes);
+            }
+        }
+        let next_line = self.start_line + collected.lines;
+        Ok((
+            collected.content,
+            ReadLinesOutput {
+                start_line: self.start_line,
+                lines: collected.lines,
+                total_lines,
+                truncated_lines: collected.truncated_lines,
+                has_more: next_line <= total_lines,
+                next_line,
+            },
+        ))
+    }
+
+    fn wants(&self, line_number: usize, collected: &Collected) -> bool {
+        line_number >= self.start_line && collected.lines < self.count && !collected.full
+    }
+}
+
+#[derive(Default)]
+struct Collected {
+    content: String,
+    lines: usize,
+    truncated_lines: usize,
+    /// No more lines fit in the maximum read size.
+    full: bool,
+}
+
+impl Collected {
+    fn push(&mut self, number: usize, line: &[u8], length: usize, max_bytes: usize) {
+        let (text, truncated) = format_line(number, line, length);
+        if !self.content.is_empty() && self.content.len() + text.len() > max_bytes {
+            self.full = true;
+            return;
+        }
+        self.content.push_str(&text);
+        self.lines += 1;
+        self.truncated_lines += usize::from(truncated);
+    }
+}
+
+/// Format a line as `<number>\t<text>\n`, `kept` is the start of a line `length` bytes long.
+fn format_line(number: usize, kept: &[u8], length: usize) -> (String, bool) {
+    if kept.len() == length {
+        let line = kept.strip_suffix(b"\r").unwrap_or(kept);
+        return (
+            format!("{number:>6}\t{}\n", String::from_utf8_lossy(line)),
+            false,
+        );
+    }
+    // don't cut a character in the middle
+    let valid = match std::str::from_utf8(kept) {
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        _ => kept.len(),
+    };
+    let omitted = length - valid;
+    let text = String::from_utf8_lossy(&kept[..valid]);
+    (
+        format!("{number:>6}\t{text}… [{omitted} bytes truncated]\n"),
+        true,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::Scratch;
+    use super::*;
+
+    fn window(start_line: usize, count: usize, max_line_length: usize) -> LineWindow {
+        LineWindow {
+            start_line,
+            count,
+            max_line_length,
+            max_bytes: 1 << 20,
+        }
+    }
+
+    #[test]
+    fn marks_truncated_lines() {
+        assert_eq!(
+            format_line(1, b"abc", 3),
+            ("     1\tabc\n".to_string(), false)
+        );
+        assert_eq!(format_line(12, b"abc\r", 4).0, "    12\tabc\n");
+        assert_eq!(
+            format_line(2, b"abc", 10),
+            ("     2\tabc… [7 bytes truncated]\n".to_string(), true)
+        );
+        // the start of a cut character is left out too
+        assert_eq!(
+            format_line(3, "aé".as_bytes()[..2].as_ref(), 3).0,
+            "     3\ta… [2 bytes truncated]\n"
+        );
+    }
+
+    #[test]
+    fn reads_a_window_of_lines() {
+        let dir = Scratch::new("read-lines");
+        let path = dir.write("file", format!("one\n{}\nthree\nfour", "x".repeat(10)));
+        let (content, output) = window(2, 2, 5).read(&path).unwrap();
+        assert_eq!(
+            content,
+            "     2\txxxxx… [5 bytes truncated]\n     3\tthree\n"
+        );
+        assert_eq!((output.lines, output.total_lines), (2, 4));
+        assert_eq!(output.truncated_lines, 1);
+        assert!(output.has_more);
+        assert_eq!(output.next_line, 4);
+
+        let (content, output) = window(4, usize::MAX, 4).read(&path).unwrap();
+        assert_eq!(content, "     4\tfour\n");
+        assert!(!output.has_more);
+        let (content, output) = window(5, usize::MAX, 4).read(&path).unwrap();
+        assert_eq!((content.as_str(), output.lines), ("", 0));
+    }
+}
@@ -0,0 +1,416 @@
+//! Moving and renaming files and directories, across file systems too.
+//!
+//! `fs.rename` and `fs.move` are the same operation: a `rename(2)` when source and
+//! destination are on the same file system, otherwise a copy of everything, attributes
+//! included, to a temporary sibling of the destination which is renamed over it, after
+//! which the source is removed. Either way the destination never holds a partial copy.
+
+use std::{io, os::unix::fs::MetadataExt, path::Path, sync::Arc};
+
+use mcp_core::{Content, handler::TypedToolHandler};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{
+    FsContext, atomic,
+    copy::{Copier, CopyOptions, CopyReport, ExistingMode},
+    jail::PathJail,
+    journal::Target,
+};
+use crate::{embed, system::mode};
+
+/// Parameters for renaming a file.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct RenameParams {
+    /// Current path of the file.
+    pub old_path: String,
+    /// New path for the file.
+    pub new_path: String,
+    /// Fail rather than replace an existing file at the new path.
+    #[serde(default)]
+    pub no_clobber: bool,
+}
+
+/// Parameters for moving a file.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct MoveParams {
+    /// Current path of the file to be moved.
+    pub source_path: String,
+    /// Destination path where the file should be moved to.
+    pub destination_path: String,
+    /// Fail rather than replace an existing file at the destination.
+    #[serde(default)]
+    pub no_clobber: bool,
+}
+
+pub struct FsRename {
+    pub(super) ctx: Arc<FsContext>,
+}
+
+impl TypedToolHandler for FsRename {
+    type Params = RenameParams;
+    fn name(&self) -> &'static str {
+        "fs.rename"
+    }
+
+    fn description(&self) -> &'static str {
+        embed!("tools/fs/rename")
+    }
+
+    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        move_entry(
+            &self.ctx,
+            self.name(),
+            &params.old_path,
+            &params.new_path,
+            params.no_clobber,
+        )
+        .await
+    }
+}
+
+pub struct FsMove {
+    pub(super) ctx: Arc<FsContext>,
+}
+
+impl TypedToolHandler for FsMove {
+    type Params = MoveParams;
+    fn name(&self) -> &'static str {
+        "fs.move"
+    }
+
+    fn description(&self) -> &'static str {
+        embed!("tools/fs/move")
+    }
+
+    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        move_entry(
+            &self.ctx,
+            self.name(),
+            &params.source_path,
+            &params.destination_path,
+            params.no_clobber,
+        )
+        .await
+    }
+}
+
+async fn move_entry(
+    ctx: &FsContext,
+    tool: &'static str,
+    from: &str,
+    to: &str,
+    no_clobber: bool,
+) -> mcp_core::ToolResult<Vec<Content>> {
+    let source = ctx.entry(from).await?;
+    let destination = ctx.entry(to).await?;
+    let metadata = tokio::fs::symlink_metadata(&source)
+        .await
+        .map_err(mcp_core::ToolError::execution)?;
+    if destination.starts_with(&source) && destination != source {
+        return Err(mcp_core::ToolError::validation(format!(
+            "can't move {from} into itself"
+        )));
+    }
+    let existing = tokio::fs::symlink_metadata(&destination).await.ok();
+    if existing.is_some() && no_clobber && destination != source {
+        return Err(mcp_core::ToolError::validation(format!(
+            "{to} already exists"
+        )));
+    }
+    if ctx.mode.guard(tool)? {
+        let parent = destination.parent().unwrap_or(Path::new("/"));
+        let cross_device = tokio::fs::metadata(parent)
+            .await
+            .is_ok_and(|parent| parent.dev() != metadata.dev());
+        return Ok(mode::dry_run(
+            tool,
+            json!({
+                "from": from,
+                "to": to,
+                "overwrites": existing.is_some(),
+                "cross_device": cross_device,
+            }),
+        ));
+    }
+    let snapshot = ctx
+        .journal
+        .snapshot(vec![Target::Moved {
+            from: source.clone(),
+            to: destination.clone(),
+        }])
+        .await;
+    // the destination may have appeared since it was checked
+    let no_clobber = no_clobber && destination != source;
+    let renamed = {
+        let (source, destination) = (source.clone(), destination.clone());
+        tokio::task::spawn_blocking(move || rename(&source, &destination, no_clobber))
+            .await
+            .map_err(mcp_core::ToolError::execution)?
+    };
+    let output = match renamed {
+        Ok(()) => json!({ "copied": false }),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            let jail = ctx.jail.clone();
+            let report = tokio::task::spawn_blocking(move || {
+                move_across_devices(jail, &source, &destination, existing.is_some(), no_clobber)
+            })
+            .await
+            .map_err(mcp_core::ToolError::execution)?
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::AlreadyExists if no_clobber => {
+                    mcp_core::ToolError::validation(format!("{to} already exists"))
+                }
+                _ => mcp_core::ToolError::execution(e),
+            })?;
+            let mut output = serde_json::to_value(&report).expect("invalid json");
+            output["copied"] = true.into();
+            output
+        }
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists && no_clobber => {
+            return Err(mcp_core::ToolError::validation(format!(
+                "{to} already exists"
+            )));
+        }
+        Err(e) => return Err(mcp_core::ToolError::execution(e)),
+    };
+    ctx.journal.record(tool, snapshot);
+    Ok(vec![Content::text(output.to_string())])
+}
+
+/// Move by copying to a temporary sibling of the destination, renaming it over the
+/// destination and removing the source, with the same outcome as `rename(2)`.
+pub(super) fn move_across_devices(
+    jail: PathJail,
+    source: &Path,
+    destination: &Path,
+    replaces: bool,
+    no_clobber: bool,
+) -> io::Result<CopyReport> {
+    let metadata = std::fs::symlink_metadata(source)?;
+    if replaces {
+        let existing = std::fs::symlink_metadata(destination)?;
+        if existing.is_dir() != metadata.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "{} already exists and can't be replaced by {}",
+                    destination.display(),
+                    source.display()
+                ),
+            ));
+        }
+        // rename(2) only replaces empty directories
+        if existing.is_dir() && std::fs::read_dir(destination)?.next().is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::DirectoryNotEmpty,
+                format!("{} is not empty", destination.display()),
+            ));
+        }
+    }
+    let temp = atomic::temp_path(destination);
+    let mut options = CopyOptions::archive(ExistingMode::Error);
+    // only root may give files away
+    options.preserve_ownership = uzers::get_effective_uid() == 0;
+    let mut copier = Copier::new(jail, options, false);
+    let copied = copier.copy(source, &temp).and_then(|()| {
+        if copier.report.skipped > 0 {
+            return Err(io::Error::other(format!(
+                "{} entries of {} can't be copied, nothing was moved",
+                copier.report.skipped,
+                source.display()
+            )));
+        }
+        if replaces && metadata.is_dir() {
+            std::fs::remove_dir(destination)?;
+        }
+        rename(&temp, destination, no_clobber)
+    });
+    if let Err(e) = copied {
+        let _ = remove(&temp);
+        return Err(e);
+    }
+    remove(source)?;
+    Ok(copier.report)
+}
+
+fn remove(path: &Path) -> io::Result<()> {
+    if std::fs::symlink_metadata(path)?.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    }
+}
+
+/// `rename(2)`, failing with `AlreadyExists` rather than replacing the destination when
+/// `no_clobber` is set. The check is part of the rename where the file system supports
+/// it, and made just before it otherwise.
+fn rename(source: &Path, destination: &Path, no_clobber: bool) -> io::Result<()> {
+    if !no_clobber {
+        return std::fs::rename(source, destination);
+    }
+    #[cfg(target_os = "linux")]
+    {
+        use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+        let c_path = |path: &Path| CString::new(path.as_os_str().as_bytes());
+        let (from, to) = (c_path(source)?, c_path(destination)?);
+        // SAFETY: both paths are nul terminated strings which outlive the call
+        let renamed = unsafe {
+            libc::renameat2(
+                libc::AT_FDCWD,
+                from.as_ptr(),
+                libc::AT_FDCWD,
+                to.as_ptr(),
+                libc::RENAME_NOREPLACE,
+            )
+        };
+        if renamed == 0 {
+            return Ok(());
+        }
+        let e = io::Error::last_os_error();
+        // the file system, or the kernel, can't rename without replacing
+        if !matches!(e.raw_os_error(), Some(libc::EINVAL | libc::ENOSYS)) {
+            return Err(e);
+        }
+    }
+    if std::fs::symlink_metadata(destination).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", destination.display()),
+        ));
+    }
+    std::fs::rename(source, destination)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use serde_json::{Value, json};
+
+    use super::super::{
+        Fs,
+        testing::{Scratch, call_json, context},
+    };
+    use super::*;
+
+    async fn move_to(params: Value) -> mcp_core::ToolResult<Value> {
+        let tool = FsMove {
+            ctx: context(Fs::default()),
+        };
+        call_json(&tool, params).await
+    }
+
+    #[tokio::test]
+    async fn refuses_to_clobber_when_asked() {
+        let dir = Scratch::new("move-no-clobber");
+        dir.write("a", "a");
+        dir.write("b", "b");
+        let params = |no_clobber| {
+            json!({
+                "source_path": dir.param("a"),
+                "destination_path": dir.param("b"),
+                "no_clobber": no_clobber,
+            })
+        };
+        let error = move_to(params(true)).await.unwrap_err();
+        assert!(
+            matches!(error, mcp_core::ToolError::InvalidParameters(_)),
+            "{error}"
+        );
+        assert_eq!((dir.read("a"), dir.read("b")), ("a".into(), "b".into()));
+        move_to(params(false)).await.unwrap();
+        assert!(!dir.join("a").exists());
+        assert_eq!(dir.read("b"), "a");
+    }
+
+    #[test]
+    fn never_replaces_without_clobbering() {
+        let dir = Scratch::new("rename-no-clobber");
+        dir.write("a", "a");
+        dir.write("b", "b");
+        let error = rename(&dir.join("a"), &dir.join("b"), true).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(dir.read("b"), "b");
+        rename(&dir.join("a"), &dir.join("c"), true).unwrap();
+        assert_eq!(dir.read("c"), "a");
+    }
+
+    #[tokio::test]
+    async fn moves_directories() {
+        let dir = Scratch::new("move-dir");
+        dir.write("src/sub/file", "file");
+        let output = move_to(json!({
+            "source_path": dir.param("src"),
+            "destination_path": dir.param("dst"),
+            "no_clobber": true,
+        }))
+        .await
+        .unwrap();
+        assert_eq!(output["copied"], false);
+        assert!(!dir.join("src").exists());
+        assert_eq!(dir.read("dst/sub/file"), "file");
+
+        let error = move_to(json!({
+            "source_path": dir.param("dst"),
+            "destination_path": dir.param("dst/sub/inner"),
+        }))
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("into itself"), "{error}");
+    }
+
+    #[test]
+    fn moves_across_devices_by_copying() {
+        let dir = Scratch::new("move-across");
+        dir.write("src/sub/file", "file");
+        symlink("sub/file", dir.join("src/link")).unwrap();
+        let report = move_across_devices(
+            PathJail::default(),
+            &dir.join("src"),
+            &dir.join("dst"),
+            false,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            (report.files, report.directories, report.symlinks),
+            (1, 2, 1)
+        );
+        assert!(!dir.join("src").exists());
+        assert_eq!(dir.read("dst/sub/file"), "file");
+        assert_eq!(
+            std::fs::read_link(dir.join("dst/link")).unwrap(),
+            Path::new("sub/file")
+        );
+
+        // an existing destination is only replaced when it's meant to be
+        dir.write("other", "other");
+        let error = move_across_devices(
+            PathJail::default(),
+            &dir.join("other"),
+            &dir.join("dst/sub/file"),
+            false,
+            true,
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(dir.read("other"), "other");
+        assert_eq!(
+            std::fs::read_dir(dir.join("dst/sub")).unwrap().count(),
+            1,
+            "the temporary copy is left"
+        );
+        move_across_devices(
+            PathJail::default(),
+            &dir.join("other"),
+            &dir.join("dst/sub/file"),
+            true,
+            false,
+        )
+        .unwrap();
+        assert_eq!(dir.read("dst/sub/file"), "other");
+    }
+}
@@ -0,0 +1,134 @@
+//! The files of the machine as mcp resources.
+//!
+//! Any file is the resource `file://llmidium/<path>`, as the resource template says, and
+//! the files of the configured directories are listed as resources of their own.
+
+use std::path::{Path, PathBuf};
+
+use mcp_core::{Resource, ResourceContents, handler::ResourceError};
+
+use super::{encoding, info, jail::PathJail, walk::EntryType};
+use crate::{
+    embed,
+    system::{ListedResource, ResourceTemplate},
+};
+
+pub const FS_RESOURCE: &str = "file://llmidium";
+
+/// The uri of the file at an absolute `path`.
+pub fn uri(path: &Path) -> String {
+    format!("{FS_RESOURCE}{}", path.display())
+}
+
+pub fn resource_templates() -> Vec<ResourceTemplate> {
+    vec![ResourceTemplate {
+        uri_template: format!("{FS_RESOURCE}/{{path}}"),
+        name: "fs".to_string(),
+        description: Some(embed!("resources/fs").to_owned()),
+        mime_type: None,
+    }]
+}
+
+/// The files directly in `directories`, each directory sorted by name, blocking on the file
+/// system. Directories out of the jail, and denied files, are left out.
+pub fn list(jail: &PathJail, directories: &[PathBuf]) -> Vec<ListedResource> {
+    let mut resources = Vec::new();
+    for directory in directories {
+        let entries = jail
+            .check(directory, true)
+            .map_err(std::io::Error::other)
+            .and_then(std::fs::read_dir);
+        let mut entries = match entries {
+            Ok(entries) => entries
+                .flatten()
+                .map(|entry| entry.path())
+                .collect::<Vec<_>>(),
+            Err(e) => {
+                tracing::warn!(?directory, %e, "can't list resources");
+                continue;
+            }
+        };
+        entries.sort();
+        for path in entries {
+            // symlinks are listed when they lead to a file within the jail
+            let Ok(resolved) = jail.check(&path, true) else {
+                continue;
+            };
+            let Ok(metadata) = std::fs::metadata(&resolved) else {
+                continue;
+            };
+            if !metadata.is_file() {
+                continue;
+            }
+            resources.push(ListedResource {
+                resource: Resource {
+                    uri: uri(&path),
+                    name: path
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into_owned(),
+                    description: None,
+                    mime_type: info::mime_type(&resolved, EntryType::File)
+                        .unwrap_or_else(|| "application/octet-stream".to_string()),
+                    annotations: None,
+                },
+                size: Some(metadata.len()),
+            });
+        }
+    }
+    resources
+}
+
+/// Read the file behind `uri`, text as it is and binary content as a base64 blob. Files of
+/// more than `max_bytes` are refused, `fs.read` reads them part by part.
+pub async fn read(
+    jail: &PathJail,
+    max_bytes: u64,
+    uri: &str,
+) -> Result<ResourceContents, ResourceError> {
+    use tokio::io::AsyncReadExt;
+
+    let Some(path) = uri.strip_prefix(FS_RESOURCE) else {
+        return Err(ResourceError::NotFound(format!("{uri} is not a file")));
+    };
+    let path = jail.resolve(path).await.map_err(ResourceError::execution)?;
+    let file = tokio::fs::File::open(&path)
+        .await
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => ResourceError::NotFound(format!("{uri} doesn't exist")),
+            _ => ResourceError::execution(e),
+        })?;
+    let size = file
+        .metadata()
+        .await
+        .map_err(ResourceError::execution)?
+        .len();
+    if size > max_bytes {
+        return Err(ResourceError::ExecutionError(format!(
+            "{uri} has {size} bytes, more than the {max_bytes} bytes a resource can have, read it with fs.read"
+        )));
+    }
+    // the file may have grown since
+    let mut content = Vec::with_capacity(size as usize);
+    file.take(max_bytes)
+        .read_to_end(&mut content)
+        .await
+        .map_err(ResourceError::execution)?;
+    let binary = encoding::is_binary(&content);
+    let mime_type = Some(encoding::mime_type(&path, binary));
+    let uri = uri.to_string();
+    Ok(if binary {
+        ResourceContents::BlobResourceContents {
+            uri,
+            mime_type,
+            blob: encoding::Encoding::Base64.encode(&content),
+        }
+    } else {
+        ResourceContents::TextResourceContents {
+            uri,
+            mime_type,
+            text: encoding::Encoding::Utf8.encode(&content),
+        }
+    })
+}
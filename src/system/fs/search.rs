@@ -0,0 +1,331 @@
+//! Searching the content of files, as `grep` would.
+
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use mcp_core::{Content, handler::TypedToolHandler};
+use regex::{Regex, RegexBuilder};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    FsContext, encoding,
+    read_lines::DEFAULT_MAX_LINE_LENGTH,
+    walk::{self, WalkOptions},
+};
+use crate::embed;
+
+/// Matches returned unless the request says otherwise.
+pub const DEFAULT_MAX_RESULTS: usize = 100;
+/// Lines longer than this are only searched in their first bytes, so a huge single-line
+/// file doesn't end up in memory.
+pub const MAX_LINE_BYTES: usize = 64 * 1024;
+
+/// Parameters for searching files.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct SearchParams {
+    /// Regular expression to search for, or text when `literal` is set.
+    pub pattern: String,
+    /// File, or directory searched recursively.
+    pub path: String,
+    /// Search for the pattern as is rather than as a regular expression.
+    #[serde(default)]
+    pub literal: bool,
+    /// Ignore case when matching.
+    #[serde(default)]
+    pub ignore_case: bool,
+    /// Number of lines returned before and after each matching line.
+    pub context: Option<usize>,
+    /// Maximum number of matching lines returned, 100 by default.
+    pub max_results: Option<usize>,
+    /// Globs of the files to search, relative to `path`, all files by default.
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(flatten)]
+    pub walk: WalkOptions,
+}
+
+/// A line matching the pattern.
+#[derive(Debug, Serialize)]
+pub struct SearchMatch {
+    pub path: PathBuf,
+    /// Number of the line, starting at 1.
+    pub line: usize,
+    /// Character the first match on the line starts at, starting at 1.
+    pub column: usize,
+    /// The line, cut around the match when it is too long.
+    pub text: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub before: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SearchOutput {
+    pub matches: Vec<SearchMatch>,
+    pub files_searched: usize,
+    /// Files left out because they are binary or can't be read.
+    pub files_skipped: usize,
+    /// Whether the search stopped at `max_results`, there may be more matches.
+    pub limit_reached: bool,
+}
+
+pub struct FsSearch {
+    pub(super) ctx: Arc<FsContext>,
+}
+
+impl TypedToolHandler for FsSearch {
+    type Params = SearchParams;
+    fn name(&self) -> &'static str {
+        "fs.search"
+    }
+
+    fn description(&self) -> &'static str {
+        embed!("tools/fs/search")
+    }
+
+    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        let root = self.ctx.path(&params.path).await?;
+        let pattern = if params.literal {
+            regex::escape(&params.pattern)
+        } else {
+            params.pattern.clone()
+        };
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(params.ignore_case)
+            .build()
+            .map_err(mcp_core::ToolError::validation)?;
+        let walk = walk::walk(&self.ctx.jail, &root, &params.include, &params.walk)
+            .map_err(mcp_core::ToolError::validation)?;
+        let searcher = Searcher {
+            regex,
+            context: params.context.unwrap_or(0),
+            max_results: params.max_results.unwrap_or(DEFAULT_MAX_RESULTS),
+        };
+        let output = tokio::task::spawn_blocking(move || searcher.search(walk))
+            .await
+            .map_err(mcp_core::ToolError::execution)?;
+        Ok(vec![Content::text(
+            serde_json::to_string(&output).expect("invalid json"),
+        )])
+    }
+}
+
+struct Searcher {
+    regex: Regex,
+    context: usize,
+    max_results: usize,
+}
+
+impl Searcher {
+    fn search(&self, walk: ignore::Walk) -> SearchOutput {
+        let mut output = SearchOutput::default();
+        for entry in walk.flatten() {
+            if !entry
+                .file_type()
+                .is_some_and(|file_type| file_type.is_file())
+            {
+                continue;
+            }
+            if output.matches.len() >= self.max_results {
+                output.limit_reached = true;
+                break;
+            }
+            match self.search_file(entry.path(), &mut output) {
+                Ok(true) => output.files_searched += 1,
+                Ok(false) | Err(_) => output.files_skipped += 1,
+            }
+        }
+        output
+    }
+
+    /// Search a file for matching lines, returns `false` when the file is binary.
+    fn search_file(&self, path: &Path, output: &mut SearchOutput) -> io::Result<bool> {
+        let matches = &mut output.matches;
+        let mut reader = BufReader::new(std::fs::File::open(path)?);
+        if encoding::is_binary(reader.fill_buf()?) {
+            return Ok(false);
+        }
+        let mut before = VecDeque::with_capacity(self.context);
+        // matches still waiting for lines after them, with the number of lines they wait for
+        let mut pending: Vec<(usize, usize)> = Vec::new();
+        let mut buf = Vec::new();
+        let mut number = 0;
+        loop {
+            buf.clear();
+            if !read_line(&mut reader, &mut buf, MAX_LINE_BYTES)? {
+                break;
+            }
+            number += 1;
+            let raw = buf.strip_suffix(b"\r").unwrap_or(&buf);
+            let line = String::from_utf8_lossy(raw);
+
+            pending.retain_mut(|(idx, left)| {
+                matches[*idx].after.push(excerpt(&line, 0));
+                *left -= 1;
+                *left > 0
+            });
+            let full = matches.len() >= self.max_results;
+            match self.regex.find(&line) {
+                Some(_) if full => {
+                    output.limit_reached = true;
+                    if pending.is_empty() {
+                        break;
+                    }
+                }
+                Some(found) => {
+                    matches.push(SearchMatch {
+                        path: path.to_path_buf(),
+                        line: number,
+                        column: line[..found.start()].chars().count() + 1,
+                        text: excerpt(&line, found.start()),
+                        before: before.drain(..).collect(),
+                        after: Vec::new(),
+                    });
+                    if self.context > 0 {
+                        pending.push((matches.len() - 1, self.context));
+                    }
+                }
+                None if full && pending.is_empty() => break,
+                None if self.context > 0 => {
+                    if before.len() == self.context {
+                        before.pop_front();
+                    }
+                    before.push_back(excerpt(&line, 0));
+                }
+                None => {}
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Read the next line into `buf`, without its newline, keeping at most `max` bytes of it
+/// and skipping the rest. Returns `false` at the end of the file.
+fn read_line(reader: &mut impl BufRead, buf: &mut Vec<u8>, max: usize) -> io::Result<bool> {
+    let mut read = false;
+    loop {
+        let chunk = reader.fill_buf()?;
+        if chunk.is_empty() {
+            return Ok(read);
+        }
+        read = true;
+        let newline = chunk.iter().position(|&b| b == b'\n');
+        let line = &chunk[..newline.unwrap_or(chunk.len())];
+        let keep = max.saturating_sub(buf.len()).min(line.len());
+        buf.extend_from_slice(&line[..keep]);
+        let consumed = line.len() + newline.map_or(0, |_| 1);
+        reader.consume(consumed);
+        if newline.is_some() {
+            return Ok(true);
+        }
+    }
+}
+
+/// The part of a line around `start`, so a huge minified line doesn't end up in the output.
+fn excerpt(line: &str, start: usize) -> String {
+    if line.len() <= DEFAULT_MAX_LINE_LENGTH {
+        return line.to_string();
+    }
+    let mut from = start.saturating_sub(DEFAULT_MAX_LINE_LENGTH / 4);
+    while !line.is_char_boundary(from) {
+        from -= 1;
+    }
+    let mut to = (from + DEFAULT_MAX_LINE_LENGTH).min(line.len());
+    while !line.is_char_boundary(to) {
+        to -= 1;
+    }
+    let prefix = if from > 0 { "…" } else { "" };
+    let suffix = if to < line.len() { "…" } else { "" };
+    format!("{prefix}{}{suffix}", &line[from..to])
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::super::{
+        Fs,
+        testing::{Scratch, call_json, context},
+    };
+    use super::*;
+
+    async fn search(params: Value) -> Value {
+        let tool = FsSearch {
+            ctx: context(Fs::default()),
+        };
+        call_json(&tool, params).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn returns_the_lines_around_each_match() {
+        let dir = Scratch::new("search-context");
+        dir.write("file", "a\nb\nmatch\nc\nd\ne\nmatch\n");
+        let output = search(json!({"pattern": "match", "path": dir.param(""), "context": 1})).await;
+        let matches = &output["matches"];
+        assert_eq!(matches[0]["line"], 3);
+        assert_eq!(matches[0]["before"], json!(["b"]));
+        assert_eq!(matches[0]["after"], json!(["c"]));
+        assert_eq!(matches[1]["line"], 7);
+        assert_eq!(matches[1]["before"], json!(["e"]));
+        assert!(matches[1].get("after").is_none());
+        assert_eq!(output["files_searched"], 1);
+    }
+
+    #[tokio::test]
+    async fn ignores_case_when_asked() {
+        let dir = Scratch::new("search-case");
+        dir.write("file", "Foo\nfoo\n");
+        let output = search(json!({"pattern": "foo", "path": dir.param("file")})).await;
+        assert_eq!(output["matches"].as_array().unwrap().len(), 1);
+        let output =
+            search(json!({"pattern": "FOO", "path": dir.param("file"), "ignore_case": true})).await;
+        assert_eq!(output["matches"].as_array().unwrap().len(), 2);
+        assert_eq!(output["matches"][0]["column"], 1);
+    }
+
+    #[tokio::test]
+    async fn stops_at_max_results() {
+        let dir = Scratch::new("search-max");
+        dir.write("a", "x\nx\n");
+        dir.write("b", "x\n");
+        let output = search(json!({"pattern": "x", "path": dir.param(""), "max_results": 2})).await;
+        assert_eq!(output["matches"].as_array().unwrap().len(), 2);
+        assert_eq!(output["limit_reached"], true);
+        let output = search(json!({"pattern": "x", "path": dir.param(""), "max_results": 3})).await;
+        assert_eq!(output["matches"].as_array().unwrap().len(), 3);
+        assert_eq!(output["limit_reached"], false);
+    }
+
+    #[test]
+    fn bounds_the_line_length() {
+        let mut reader = BufReader::with_capacity(4, &b"0123456789\r\nab\nc"[..]);
+        let mut buf = Vec::new();
+        let mut lines = Vec::new();
+        while read_line(&mut reader, &mut buf, 5).unwrap() {
+            lines.push(String::from_utf8(std::mem::take(&mut buf)).unwrap());
+        }
+        assert_eq!(lines, ["01234", "ab", "c"]);
+    }
+
+    #[tokio::test]
+    async fn searches_the_start_of_huge_lines() {
+        let dir = Scratch::new("search-long");
+        let mut line = "x".repeat(MAX_LINE_BYTES - 5);
+        line.push_str("start");
+        line.push_str(&"x".repeat(MAX_LINE_BYTES));
+        line.push_str("end\nend\n");
+        dir.write("file", line);
+        let output = search(json!({"pattern": "start|end", "path": dir.param("file")})).await;
+        let matches = output["matches"].as_array().unwrap();
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0]["column"], MAX_LINE_BYTES - 4);
+        assert!(matches[0]["text"].as_str().unwrap().len() <= DEFAULT_MAX_LINE_LENGTH + 6);
+        assert_eq!(matches[1]["line"], 2);
+    }
+}
@@ -0,0 +1,94 @@
+//! Helpers shared by the tests of the `fs.*` tools.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use mcp_core::{Content, handler::TypedToolHandler};
+use serde_json::Value;
+
+use super::{Fs, FsContext};
+
+/// A fresh directory below the temporary directory, resolved so its own path doesn't
+/// contain symlinks (e.g. `/tmp` on macOS), and removed again on drop.
+pub struct Scratch(PathBuf);
+
+impl Scratch {
+    pub fn new(name: &str) -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "llmidium-{}-{}-{name}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        Scratch(std::fs::canonicalize(dir).unwrap())
+    }
+
+    /// Write `content` to `path` below the directory, creating its parents.
+    pub fn write(&self, path: &str, content: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.0.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    pub fn read(&self, path: &str) -> String {
+        std::fs::read_to_string(self.0.join(path)).unwrap()
+    }
+
+    /// The path of `path` below the directory, as a tool parameter.
+    pub fn param(&self, path: &str) -> String {
+        self.0.join(path).to_string_lossy().into_owned()
+    }
+}
+
+impl std::ops::Deref for Scratch {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// The context of a session of the server serving `fs`.
+pub fn context(fs: Fs) -> Arc<FsContext> {
+    Arc::new(FsContext::new(&fs))
+}
+
+/// Call a tool with JSON parameters, returning the text of its contents.
+pub async fn call<T: TypedToolHandler>(
+    tool: &T,
+    params: Value,
+) -> mcp_core::ToolResult<Vec<String>> {
+    let params = serde_json::from_value(params).expect("invalid parameters");
+    let contents = tool.call(params).await?;
+    Ok(contents
+        .into_iter()
+        .map(|content| match content {
+            Content::Text(text) => text.text,
+            content => panic!("unexpected content {content:?}"),
+        })
+        .collect())
+}
+
+/// Call a tool whose last content is JSON, and parse it.
+pub async fn call_json<T: TypedToolHandler>(
+    tool: &T,
+    params: Value,
+) -> mcp_core::ToolResult<Value> {
+    let texts = call(tool, params).await?;
+    let last = texts.last().expect("no content");
+    Ok(serde_json::from_str(last).expect("invalid json"))
+}
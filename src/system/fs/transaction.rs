@@ -0,0 +1,641 @@
+//! Changes to several files which are all made, or none of them.
+//!
+//! Every operation of `fs.transaction` is checked against the files as the previous
+//! operations leave them before anything is touched. The changes are then made one by one,
+//! deleted and replaced files are kept aside, and the changes already made are rolled back
+//! when a later one fails.
+
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use mcp_core::{Content, handler::TypedToolHandler};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    FsContext, atomic, edit, encoding::Encoding, jail::PathJail, journal::Target,
+    rename::move_across_devices,
+};
+use crate::{embed, system::mode};
+
+/// Parameters for changing several files at once.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct TransactionParams {
+    /// Operations made in order, each one sees the files as the previous ones leave them.
+    pub operations: Vec<Operation>,
+}
+
+/// A change to one file, files are created along with their missing parent directories.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    /// Create a file which doesn't exist yet.
+    Create {
+        path: String,
+        data: String,
+        /// How `data` is encoded, `utf8` by default.
+        encoding: Option<Encoding>,
+    },
+    /// Replace the content of a file, creating it when it doesn't exist.
+    Write {
+        path: String,
+        data: String,
+        /// How `data` is encoded, `utf8` by default.
+        encoding: Option<Encoding>,
+        #[serde(flatten)]
+        precondition: atomic::Precondition,
+    },
+    /// Replace text in a file, as `fs.edit`.
+    Edit {
+        path: String,
+        old_text: String,
+        new_text: String,
+        #[serde(default)]
+        replace_all: bool,
+        #[serde(flatten)]
+        precondition: atomic::Precondition,
+    },
+    /// Delete a file.
+    Delete {
+        path: String,
+        #[serde(flatten)]
+        precondition: atomic::Precondition,
+    },
+    /// Rename a file, `to` must not exist.
+    Rename { from: String, to: String },
+}
+
+impl Operation {
+    fn name(&self) -> &'static str {
+        match self {
+            Operation::Create { .. } => "create",
+            Operation::Write { .. } => "write",
+            Operation::Edit { .. } => "edit",
+            Operation::Delete { .. } => "delete",
+            Operation::Rename { .. } => "rename",
+        }
+    }
+
+    fn path(&self) -> &str {
+        match self {
+            Operation::Create { path, .. }
+            | Operation::Write { path, .. }
+            | Operation::Edit { path, .. }
+            | Operation::Delete { path, .. } => path,
+            Operation::Rename { from, .. } => from,
+        }
+    }
+
+    fn precondition(&self) -> Option<&atomic::Precondition> {
+        match self {
+            Operation::Write { precondition, .. }
+            | Operation::Edit { precondition, .. }
+            | Operation::Delete { precondition, .. } => Some(precondition),
+            Operation::Create { .. } | Operation::Rename { .. } => None,
+        }
+    }
+}
+
+/// What an operation does, or would do.
+#[derive(Debug, Serialize)]
+pub struct OperationReport {
+    pub op: &'static str,
+    pub path: String,
+    /// New path of a renamed file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    /// Size of the file once written.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<usize>,
+    /// Replacements made by an edit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replacements: Option<usize>,
+}
+
+pub struct FsTransaction {
+    pub(super) ctx: Arc<FsContext>,
+}
+
+impl TypedToolHandler for FsTransaction {
+    type Params = TransactionParams;
+    fn name(&self) -> &'static str {
+        "fs.transaction"
+    }
+
+    fn description(&self) -> &'static str {
+        embed!("tools/fs/transaction")
+    }
+
+    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        if params.operations.is_empty() {
+            return Err(mcp_core::ToolError::validation("no operation to make"));
+        }
+        let failed = |index: usize, operation: &Operation, error: String| {
+            mcp_core::ToolError::ExecutionError(format!(
+                "operation {} ({} {}) fails, no file was changed: {error}",
+                index + 1,
+                operation.name(),
+                operation.path(),
+            ))
+        };
+
+        let mut resolved = Vec::with_capacity(params.operations.len());
+        for (index, operation) in params.operations.iter().enumerate() {
+            let target = self
+                .resolve(operation)
+                .await
+                .map_err(|e| failed(index, operation, e.to_string()))?;
+            resolved.push(target);
+        }
+        // locked in order, so that transactions sharing files can't wait for one another
+        let mut locked: Vec<&Path> = resolved
+            .iter()
+            .flat_map(|(path, to)| std::iter::once(path.as_path()).chain(to.as_deref()))
+            .collect();
+        locked.sort();
+        locked.dedup();
+        let mut locks = Vec::with_capacity(locked.len());
+        for path in locked {
+            locks.push(atomic::lock(path).await);
+        }
+
+        let mut files = Files::default();
+        let mut changes = Vec::with_capacity(params.operations.len());
+        let mut report = Vec::with_capacity(params.operations.len());
+        for (index, (operation, (path, to))) in params.operations.iter().zip(resolved).enumerate() {
+            let (change, operation_report) = files
+                .prepare(operation, path, to)
+                .await
+                .map_err(|e| failed(index, operation, e))?;
+            changes.push(change);
+            report.push(operation_report);
+        }
+        let report = serde_json::to_value(&report).expect("invalid json");
+        if self.ctx.mode.guard(self.name())? {
+            return Ok(mode::dry_run(self.name(), report));
+        }
+        let snapshot = self.ctx.journal.snapshot(targets(&changes)).await;
+        commit(&self.ctx.jail, changes).await.map_err(|e| {
+            mcp_core::ToolError::ExecutionError(format!(
+                "the transaction failed and was rolled back: {e}"
+            ))
+        })?;
+        self.ctx.journal.record(self.name(), snapshot);
+        Ok(vec![Content::text(report.to_string())])
+    }
+}
+
+impl FsTransaction {
+    /// The file an operation changes, and the new path of a renamed file.
+    async fn resolve(
+        &self,
+        operation: &Operation,
+    ) -> mcp_core::ToolResult<(PathBuf, Option<PathBuf>)> {
+        Ok(match operation {
+            Operation::Create { path, .. }
+            | Operation::Write { path, .. }
+            | Operation::Edit { path, .. } => (self.ctx.path(path).await?, None),
+            Operation::Delete { path, .. } => (self.ctx.entry(path).await?, None),
+            Operation::Rename { from, to } => {
+                (self.ctx.entry(from).await?, Some(self.ctx.entry(to).await?))
+            }
+        })
+    }
+}
+
+/// The files a transaction touches, as the operations checked so far leave them.
+#[derive(Debug, Default)]
+struct Files {
+    /// Content of each file, `None` once it's deleted or renamed. The files are keyed by
+    /// the absolute paths the jail resolves them to, so `b.txt` and `./b.txt` are the same
+    /// file.
+    content: HashMap<PathBuf, Option<Vec<u8>>>,
+}
+
+impl Files {
+    /// Content of the file at `path`, `None` when there is no such file.
+    async fn get(&self, path: &Path) -> Result<Option<Vec<u8>>, String> {
+        if let Some(content) = self.content.get(path) {
+            return Ok(content.clone());
+        }
+        match tokio::fs::symlink_metadata(path).await {
+            Ok(metadata) if metadata.is_dir() => {
+                return Err(format!("{} is a directory", path.display()));
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.to_string()),
+        }
+        tokio::fs::read(path)
+            .await
+            .map(Some)
+            .map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    async fn existing(&self, path: &Path) -> Result<Vec<u8>, String> {
+        self.get(path)
+            .await?
+            .ok_or_else(|| format!("{} doesn't exist", path.display()))
+    }
+
+    /// Check an operation and work out its change, as if the previous ones were made.
+    async fn prepare(
+        &mut self,
+        operation: &Operation,
+        path: PathBuf,
+        to: Option<PathBuf>,
+    ) -> Result<(Change, OperationReport), String> {
+        debug_assert!(path.is_absolute() && to.as_ref().is_none_or(|to| to.is_absolute()));
+        let mut report = OperationReport {
+            op: operation.name(),
+            path: operation.path().to_string(),
+            to: None,
+            size: None,
+            replacements: None,
+        };
+        if let Some(precondition) = operation.precondition() {
+            self.check(precondition, &path).await?;
+        }
+        let change = match operation {
+            Operation::Create { data, encoding, .. } => {
+                if self.get(&path).await?.is_some() {
+                    return Err(format!("{} already exists", path.display()));
+                }
+                let content = decode(data, *encoding)?;
+                report.size = Some(content.len());
+                self.content.insert(path.clone(), Some(content.clone()));
+                Change::Write {
+                    path,
+                    content,
+                    original: None,
+                }
+            }
+            Operation::Write { data, encoding, .. } => {
+                let original = self.get(&path).await?;
+                let content = decode(data, *encoding)?;
+                report.size = Some(content.len());
+                self.content.insert(path.clone(), Some(content.clone()));
+                Change::Write {
+                    path,
+                    content,
+                    original,
+                }
+            }
+            Operation::Edit {
+                old_text,
+                new_text,
+                replace_all,
+                ..
+            } => {
+                let original = self.existing(&path).await?;
+                let text = std::str::from_utf8(&original)
+                    .map_err(|_| format!("{} is not a utf-8 text file", path.display()))?;
+                let (edited, replacements) = edit::replace(text, old_text, new_text, *replace_all)?;
+                report.size = Some(edited.len());
+                report.replacements = Some(replacements);
+                let content = edited.into_bytes();
+                self.content.insert(path.clone(), Some(content.clone()));
+                Change::Write {
+                    path,
+                    content,
+                    original: Some(original),
+                }
+            }
+            Operation::Delete { .. } => {
+                self.existing(&path).await?;
+                self.content.insert(path.clone(), None);
+                Change::Delete { path }
+            }
+            Operation::Rename { to: to_path, .. } => {
+                let to = to.expect("resolved with the operation");
+                let content = self.existing(&path).await?;
+                if self.get(&to).await?.is_some() {
+                    return Err(format!("{} already exists", to.display()));
+                }
+                report.to = Some(to_path.clone());
+                self.content.insert(path.clone(), None);
+                self.content.insert(to.clone(), Some(content));
+                Change::Rename {
+                    from: path,
+                    to,
+                    content: None,
+                }
+            }
+        };
+        Ok((change, report))
+    }
+
+    /// Preconditions are about the files as they are before the transaction.
+    async fn check(&self, precondition: &atomic::Precondition, path: &Path) -> Result<(), String> {
+        if precondition.expected_sha256.is_none() && precondition.expected_mtime.is_none() {
+            return Ok(());
+        }
+        if self.content.contains_key(path) {
+            return Err(format!(
+                "{} is changed by an earlier operation, its expected_sha256 and expected_mtime can't be checked",
+                path.display()
+            ));
+        }
+        let content = self.get(path).await?;
+        precondition
+            .check(path, content.as_deref())
+            .await
+            .map_err(|conflict| format!("conflict: {conflict}"))
+    }
+}
+
+fn decode(data: &str, encoding: Option<Encoding>) -> Result<Vec<u8>, String> {
+    encoding
+        .unwrap_or(Encoding::Utf8)
+        .decode(data)
+        .map_err(|e| format!("invalid base64 data: {e}"))
+}
+
+/// A change to the file system, prepared before anything is touched.
+pub(super) enum Change {
+    Write {
+        path: PathBuf,
+        content: Vec<u8>,
+        original: Option<Vec<u8>>,
+    },
+    Delete {
+        path: PathBuf,
+    },
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+        content: Option<Vec<u8>>,
+    },
+}
+
+/// The paths `changes` touch, for the journal.
+pub(super) fn targets(changes: &[Change]) -> Vec<Target> {
+    let mut paths: Vec<&Path> = Vec::new();
+    for change in changes {
+        match change {
+            Change::Write { path, .. } | Change::Delete { path } => paths.push(path),
+            Change::Rename { from, to, .. } => paths.extend([from.as_path(), to.as_path()]),
+        }
+    }
+    let mut targets: Vec<Target> = Vec::with_capacity(paths.len());
+    for path in paths {
+        if !targets
+            .iter()
+            .any(|target| matches!(target, Target::Entry(known) if known == path))
+        {
+            targets.push(Target::Entry(path.to_path_buf()));
+        }
+    }
+    targets
+}
+
+/// A step already made, and how to revert it.
+pub(super) enum Undo {
+    Restore { path: PathBuf, content: Vec<u8> },
+    Remove { path: PathBuf },
+    Rename { from: PathBuf, to: PathBuf },
+    RemoveDirectory { path: PathBuf },
+}
+
+/// Make every change, or none of them.
+pub(super) async fn commit(jail: &PathJail, changes: Vec<Change>) -> io::Result<()> {
+    let mut done = Vec::new();
+    // deleted files are moved aside, and only removed once every change is made
+    let mut backups = Vec::new();
+    let mut result = Ok(());
+    for change in changes {
+        result = apply(jail, change, &mut done, &mut backups).await;
+        if result.is_err() {
+            break;
+        }
+    }
+    if result.is_err() {
+        for undo in done.into_iter().rev() {
+            let reverted = match &undo {
+                Undo::Restore { path, content } => atomic::write(path, content).await,
+                Undo::Remove { path } => tokio::fs::remove_file(path).await,
+                Undo::Rename { from, to } => rename(jail, from, to).await,
+                Undo::RemoveDirectory { path } => tokio::fs::remove_dir(path).await,
+            };
+            if let Err(e) = reverted {
+                tracing::error!(?e, "failed to roll back a change");
+            }
+        }
+        return result;
+    }
+    for backup in backups {
+        let _ = tokio::fs::remove_file(backup).await;
+    }
+    Ok(())
+}
+
+async fn apply(
+    jail: &PathJail,
+    change: Change,
+    done: &mut Vec<Undo>,
+    backups: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    match change {
+        Change::Write {
+            path,
+            content,
+            original,
+        } => {
+            create_parents(&path, done).await?;
+            atomic::write(&path, &content).await?;
+            done.push(match original {
+                Some(content) => Undo::Restore { path, content },
+                None => Undo::Remove { path },
+            });
+        }
+        Change::Delete { path } => {
+            let backup = atomic::temp_path(&path);
+            tokio::fs::rename(&path, &backup).await?;
+            done.push(Undo::Rename {
+                from: backup.clone(),
+                to: path,
+            });
+            backups.push(backup);
+        }
+        Change::Rename { from, to, content } => {
+            create_parents(&to, done).await?;
+            let original = match &content {
+                Some(_) => Some(tokio::fs::read(&from).await?),
+                None => None,
+            };
+            rename(jail, &from, &to).await?;
+            done.push(Undo::Rename {
+                from: to.clone(),
+                to: from,
+            });
+            if let (Some(content), Some(original)) = (content, original) {
+                atomic::write(&to, &content).await?;
+                done.push(Undo::Restore {
+                    path: to,
+                    content: original,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Create the missing parent directories of `path`, each one removed again on rollback.
+async fn create_parents(path: &Path, done: &mut Vec<Undo>) -> io::Result<()> {
+    let mut missing = Vec::new();
+    for ancestor in path.ancestors().skip(1) {
+        match tokio::fs::symlink_metadata(ancestor).await {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => missing.push(ancestor),
+            _ => break,
+        }
+    }
+    for directory in missing.into_iter().rev() {
+        match tokio::fs::create_dir(directory).await {
+            Ok(()) => done.push(Undo::RemoveDirectory {
+                path: directory.to_path_buf(),
+            }),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Rename a file, or copy it and remove the original when it moves to another file system.
+async fn rename(jail: &PathJail, from: &Path, to: &Path) -> io::Result<()> {
+    match tokio::fs::rename(from, to).await {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            let (jail, from, to) = (jail.clone(), from.to_path_buf(), to.to_path_buf());
+            tokio::task::spawn_blocking(move || move_across_devices(jail, &from, &to, false, false))
+                .await
+                .map_err(io::Error::other)?
+                .map(|_| ())
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::super::{
+        Fs,
+        testing::{Scratch, call_json, context},
+    };
+    use super::*;
+
+    async fn transaction(params: Value) -> mcp_core::ToolResult<Value> {
+        let tool = FsTransaction {
+            ctx: context(Fs::default()),
+        };
+        call_json(&tool, params).await
+    }
+
+    /// Every path below `dir`, sorted.
+    fn tree(dir: &Path) -> Vec<PathBuf> {
+        let mut paths: Vec<_> = ignore::WalkBuilder::new(dir)
+            .standard_filters(false)
+            .build()
+            .flatten()
+            .map(|entry| entry.path().strip_prefix(dir).unwrap().to_path_buf())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[tokio::test]
+    async fn rolls_back_every_change_when_one_fails() {
+        let dir = Scratch::new("transaction-rollback");
+        dir.write("a", "a");
+        dir.write("b", "b");
+        dir.write("c", "c");
+        let before = tree(&dir);
+        let changes = vec![
+            Change::Write {
+                path: dir.join("a"),
+                content: b"new a".to_vec(),
+                original: Some(b"a".to_vec()),
+            },
+            Change::Write {
+                path: dir.join("new/sub/file"),
+                content: b"new".to_vec(),
+                original: None,
+            },
+            Change::Delete {
+                path: dir.join("b"),
+            },
+            Change::Rename {
+                from: dir.join("c"),
+                to: dir.join("moved/c"),
+                content: None,
+            },
+            // the file is gone by the time the transaction is made
+            Change::Delete {
+                path: dir.join("missing"),
+            },
+        ];
+        commit(&PathJail::default(), changes).await.unwrap_err();
+        assert_eq!(tree(&dir), before);
+        assert_eq!(
+            (dir.read("a"), dir.read("b"), dir.read("c")),
+            ("a".into(), "b".into(), "c".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn makes_every_change() {
+        let dir = Scratch::new("transaction");
+        dir.write("a", "let a = 1;\n");
+        dir.write("b", "b");
+        let report = transaction(json!({"operations": [
+            {"op": "edit", "path": dir.param("a"), "old_text": "1", "new_text": "2"},
+            {"op": "rename", "from": dir.param("a"), "to": dir.param("sub/a")},
+            {"op": "delete", "path": dir.param("b")},
+            {"op": "create", "path": dir.param("b"), "data": "new b"},
+        ]}))
+        .await
+        .unwrap();
+        assert_eq!(report[0]["replacements"], 1);
+        assert_eq!(dir.read("sub/a"), "let a = 2;\n");
+        assert_eq!(dir.read("b"), "new b");
+        assert!(!dir.join("a").exists());
+    }
+
+    #[tokio::test]
+    async fn checks_preconditions_against_the_files_as_they_were() {
+        let dir = Scratch::new("transaction-precondition");
+        dir.write("a", "a");
+        let sha256 = atomic::sha256(b"a");
+        let error = transaction(json!({"operations": [
+            {"op": "write", "path": dir.param("a"), "data": "b"},
+            {"op": "delete", "path": dir.param("./a"), "expected_sha256": sha256},
+        ]}))
+        .await
+        .unwrap_err();
+        let error = error.to_string();
+        assert!(error.contains("operation 2 (delete"), "{error}");
+        assert!(
+            error.contains("is changed by an earlier operation"),
+            "{error}"
+        );
+        assert_eq!(dir.read("a"), "a");
+
+        let error = transaction(json!({"operations": [
+            {"op": "write", "path": dir.param("a"), "data": "b", "expected_sha256": atomic::sha256(b"b")},
+        ]}))
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("conflict"), "{error}");
+        transaction(json!({"operations": [
+            {"op": "write", "path": dir.param("a"), "data": "b", "expected_sha256": sha256},
+        ]}))
+        .await
+        .unwrap();
+        assert_eq!(dir.read("a"), "b");
+    }
+}
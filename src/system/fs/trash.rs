@@ -0,0 +1,561 @@
+//! The trash deleted files and directories are moved to, when it's enabled, rather than
+//! being removed for good.
+//!
+//! Each trashed item is a directory of the trash, named after the time of the deletion,
+//! which holds the entry under its own name and an `info.json` recording where it was.
+//! Items are removed for good once they are older than the retention age, and, the oldest
+//! first, once the trash holds more than its size limit.
+
+use std::{
+    fs::{self, DirBuilder},
+    io,
+    os::unix::fs::DirBuilderExt,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use mcp_core::{Content, handler::TypedToolHandler};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{FsContext, disk_usage, jail::PathJail, journal::Target, rename};
+use crate::{embed, system::mode};
+
+/// Default directory of the trash.
+pub const DEFAULT_DIRECTORY: &str = "/var/lib/llmidium/trash";
+/// Default age after which trashed items are removed for good, 30 days.
+pub const DEFAULT_MAX_AGE_SECS: u64 = 30 * 24 * 60 * 60;
+/// Default size of the trash beyond which the oldest items are removed for good, 1 GiB.
+pub const DEFAULT_MAX_BYTES: u64 = 1024 * 1024 * 1024;
+
+const INFO: &str = "info.json";
+
+/// Settings of the trash `fs.delete` and `fs.remove_directory` move entries to.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TrashConfig {
+    /// Move deleted files and directories to the trash instead of removing them.
+    pub enabled: bool,
+    /// Directory of the trash, created when needed.
+    pub directory: PathBuf,
+    /// Seconds a trashed item is kept for.
+    pub max_age_secs: u64,
+    /// Bytes the trash may hold, the oldest items are removed beyond.
+    pub max_bytes: u64,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        TrashConfig {
+            enabled: false,
+            directory: PathBuf::from(DEFAULT_DIRECTORY),
+            max_age_secs: DEFAULT_MAX_AGE_SECS,
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+}
+
+/// An entry in the trash, as its `info.json` records it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TrashItem {
+    pub id: String,
+    /// Where the entry was deleted from.
+    pub original_path: PathBuf,
+    pub deleted_at: DateTime<Utc>,
+    pub directory: bool,
+    /// Bytes of the entry, everything below a directory included.
+    pub size: u64,
+}
+
+/// The trash shared by every session.
+#[derive(Debug, Clone)]
+pub struct Trash {
+    config: TrashConfig,
+}
+
+impl Trash {
+    pub fn new(config: TrashConfig) -> Self {
+        Trash { config }
+    }
+
+    /// Whether `path` is in the trash, where deleted entries are removed for good.
+    pub fn contains(&self, path: &Path) -> bool {
+        path.starts_with(&self.config.directory)
+    }
+
+    /// The item of `path`, about to be deleted, blocking on the file system.
+    fn item(&self, path: &Path) -> io::Result<TrashItem> {
+        let metadata = fs::symlink_metadata(path)?;
+        let (_, size) = disk_usage(path)?;
+        let deleted_at = Utc::now();
+        Ok(TrashItem {
+            id: format!(
+                "{}-{:08x}",
+                deleted_at.format("%Y%m%dT%H%M%S%.6f"),
+                rand::random::<u32>()
+            ),
+            original_path: path.to_path_buf(),
+            deleted_at,
+            directory: metadata.is_dir(),
+            size,
+        })
+    }
+
+    /// Where the entry of `item` is kept.
+    fn entry(&self, item: &TrashItem) -> PathBuf {
+        let name = item.original_path.file_name().unwrap_or("entry".as_ref());
+        self.config.directory.join(&item.id).join(name)
+    }
+
+    /// Move the entry at `path` to the trash as `item`, blocking on the file system.
+    fn put(&self, jail: PathJail, item: &TrashItem) -> io::Result<()> {
+        let directory = self.config.directory.join(&item.id);
+        // deleted files may be secret, only the server may look into the trash
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&directory)?;
+        let moved = fs::write(
+            directory.join(INFO),
+            serde_json::to_vec_pretty(item).expect("invalid json"),
+        )
+        .and_then(|()| move_entry(jail, &item.original_path, &self.entry(item)));
+        if let Err(e) = moved {
+            let _ = fs::remove_dir_all(&directory);
+            return Err(e);
+        }
+        self.purge(Some(&item.id));
+        Ok(())
+    }
+
+    /// The items deleted from within `jail`, the latest first, blocking on the file system.
+    /// A session can't see, restore nor remove what was deleted out of its jail.
+    fn list(&self, jail: &PathJail) -> io::Result<Vec<TrashItem>> {
+        let mut items = self.list_all()?;
+        items.retain(|item| jail.check(&item.original_path, false).is_ok());
+        Ok(items)
+    }
+
+    /// Every item in the trash, the latest first, blocking on the file system.
+    fn list_all(&self) -> io::Result<Vec<TrashItem>> {
+        let entries = match fs::read_dir(&self.config.directory) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut items = Vec::new();
+        for entry in entries {
+            let info = entry?.path().join(INFO);
+            // anything else in the trash directory isn't an item
+            let Ok(info) = fs::read(&info) else {
+                continue;
+            };
+            if let Ok(item) = serde_json::from_slice::<TrashItem>(&info) {
+                items.push(item);
+            }
+        }
+        items.sort_by_key(|item| std::cmp::Reverse(item.deleted_at));
+        Ok(items)
+    }
+
+    fn find(&self, jail: &PathJail, id: &str) -> io::Result<TrashItem> {
+        self.list(jail)?
+            .into_iter()
+            .find(|item| item.id == id)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("there is no item {id} in the trash"),
+                )
+            })
+    }
+
+    /// Move `item` back to `destination`, which must not exist, blocking on the file system.
+    fn restore(&self, jail: PathJail, item: &TrashItem, destination: &Path) -> io::Result<()> {
+        if fs::symlink_metadata(destination).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", destination.display()),
+            ));
+        }
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        move_entry(jail, &self.entry(item), destination)?;
+        fs::remove_dir_all(self.config.directory.join(&item.id))
+    }
+
+    /// Remove `item` for good, blocking on the file system.
+    fn remove(&self, item: &TrashItem) -> io::Result<()> {
+        fs::remove_dir_all(self.config.directory.join(&item.id))
+    }
+
+    /// Remove the items past the retention age, then the oldest ones while the trash is
+    /// larger than its limit, except the item `keep`.
+    fn purge(&self, keep: Option<&str>) {
+        let items = match self.list_all() {
+            Ok(items) => items,
+            Err(e) => {
+                tracing::warn!(?e, "can't list the trash");
+                return;
+            }
+        };
+        let max_age = Duration::from_secs(self.config.max_age_secs);
+        let expired = Utc::now() - max_age;
+        let mut size = 0;
+        // the latest items are kept first
+        for item in items {
+            size += item.size;
+            let kept = Some(item.id.as_str()) == keep;
+            if !kept && (item.deleted_at < expired || size > self.config.max_bytes) {
+                tracing::debug!(id = item.id, path = ?item.original_path, "trash item removed");
+                if let Err(e) = self.remove(&item) {
+                    tracing::warn!(?e, id = item.id, "can't remove a trash item");
+                }
+            }
+        }
+    }
+}
+
+fn move_entry(jail: PathJail, from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            rename::move_across_devices(jail, from, to, false, false).map(|_| ())
+        }
+        result => result,
+    }
+}
+
+/// Move the entry at `path` to the trash instead of removing it, as `tool`. The move is
+/// journaled, so that `fs.undo` brings the entry back.
+pub(super) async fn trash(
+    ctx: &FsContext,
+    trash: &Trash,
+    tool: &'static str,
+    path: &Path,
+) -> mcp_core::ToolResult<Vec<Content>> {
+    let item = {
+        let (trash, path) = (trash.clone(), path.to_path_buf());
+        tokio::task::spawn_blocking(move || trash.item(&path))
+            .await
+            .map_err(mcp_core::ToolError::execution)?
+            .map_err(mcp_core::ToolError::execution)?
+    };
+    let snapshot = ctx
+        .journal
+        .snapshot(vec![Target::Moved {
+            from: path.to_path_buf(),
+            to: trash.entry(&item),
+        }])
+        .await;
+    let output = serde_json::to_string(&item).expect("invalid json");
+    let (trash, jail) = (trash.clone(), ctx.jail.clone());
+    tokio::task::spawn_blocking(move || trash.put(jail, &item))
+        .await
+        .map_err(mcp_core::ToolError::execution)?
+        .map_err(mcp_core::ToolError::execution)?;
+    ctx.journal.record(tool, snapshot);
+    Ok(vec![Content::text(output)])
+}
+
+fn enabled(ctx: &FsContext) -> mcp_core::ToolResult<Trash> {
+    ctx.trash.clone().ok_or_else(|| {
+        mcp_core::ToolError::ExecutionError(
+            "the trash is disabled, deleted entries are removed for good".to_string(),
+        )
+    })
+}
+
+/// Parameters for listing the trash.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct TrashListParams {
+    /// Only list the items deleted from this path or from below it.
+    pub path: Option<String>,
+}
+
+/// Parameters for restoring an item of the trash.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct TrashRestoreParams {
+    /// Id of the item, as `fs.trash_list` lists it.
+    pub id: String,
+    /// Where to restore the item, where it was deleted from by default.
+    pub path: Option<String>,
+}
+
+/// Parameters for emptying the trash.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct TrashEmptyParams {
+    /// Ids of the items to remove for good, every item by default.
+    pub ids: Option<Vec<String>>,
+    /// Only remove the items deleted before this time.
+    pub deleted_before: Option<DateTime<Utc>>,
+}
+
+pub struct FsTrashList {
+    pub(super) ctx: Arc<FsContext>,
+}
+
+impl TypedToolHandler for FsTrashList {
+    type Params = TrashListParams;
+    fn name(&self) -> &'static str {
+        "fs.trash_list"
+    }
+
+    fn description(&self) -> &'static str {
+        embed!("tools/fs/trash_list")
+    }
+
+    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        let trash = enabled(&self.ctx)?;
+        let below = match &params.path {
+            Some(path) => Some(self.ctx.entry(path).await?),
+            None => None,
+        };
+        let jail = self.ctx.jail.clone();
+        let mut items = tokio::task::spawn_blocking(move || trash.list(&jail))
+            .await
+            .map_err(mcp_core::ToolError::execution)?
+            .map_err(mcp_core::ToolError::execution)?;
+        if let Some(below) = below {
+            items.retain(|item| item.original_path.starts_with(&below));
+        }
+        let size: u64 = items.iter().map(|item| item.size).sum();
+        Ok(vec![Content::text(
+            json!({ "items": items, "size": size }).to_string(),
+        )])
+    }
+}
+
+pub struct FsTrashRestore {
+    pub(super) ctx: Arc<FsContext>,
+}
+
+impl TypedToolHandler for FsTrashRestore {
+    type Params = TrashRestoreParams;
+    fn name(&self) -> &'static str {
+        "fs.trash_restore"
+    }
+
+    fn description(&self) -> &'static str {
+        embed!("tools/fs/trash_restore")
+    }
+
+    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        let trash = enabled(&self.ctx)?;
+        let item = {
+            let (trash, jail, id) = (trash.clone(), self.ctx.jail.clone(), params.id.clone());
+            tokio::task::spawn_blocking(move || trash.find(&jail, &id))
+                .await
+                .map_err(mcp_core::ToolError::execution)?
+                .map_err(mcp_core::ToolError::execution)?
+        };
+        // the original path may be out of the jail of this session
+        let destination = match &params.path {
+            Some(path) => self.ctx.entry(path).await?,
+            None => {
+                self.ctx
+                    .entry(&item.original_path.to_string_lossy())
+                    .await?
+            }
+        };
+        if self.ctx.mode.guard(self.name())? {
+            return Ok(mode::dry_run(
+                self.name(),
+                json!({
+                    "item": item,
+                    "path": destination,
+                    "exists": tokio::fs::symlink_metadata(&destination).await.is_ok(),
+                }),
+            ));
+        }
+        let jail = self.ctx.jail.clone();
+        let restored = destination.clone();
+        tokio::task::spawn_blocking(move || trash.restore(jail, &item, &restored))
+            .await
+            .map_err(mcp_core::ToolError::execution)?
+            .map_err(mcp_core::ToolError::execution)?;
+        Ok(vec![Content::text(
+            json!({ "path": destination }).to_string(),
+        )])
+    }
+}
+
+pub struct FsTrashEmpty {
+    pub(super) ctx: Arc<FsContext>,
+}
+
+impl TypedToolHandler for FsTrashEmpty {
+    type Params = TrashEmptyParams;
+    fn name(&self) -> &'static str {
+        "fs.trash_empty"
+    }
+
+    fn description(&self) -> &'static str {
+        embed!("tools/fs/trash_empty")
+    }
+
+    async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<mcp_core::Content>> {
+        let trash = enabled(&self.ctx)?;
+        let (listed, jail) = (trash.clone(), self.ctx.jail.clone());
+        let mut items = tokio::task::spawn_blocking(move || listed.list(&jail))
+            .await
+            .map_err(mcp_core::ToolError::execution)?
+            .map_err(mcp_core::ToolError::execution)?;
+        if let Some(ids) = &params.ids {
+            if let Some(id) = ids
+                .iter()
+                .find(|id| !items.iter().any(|item| item.id == **id))
+            {
+                return Err(mcp_core::ToolError::validation(format!(
+                    "there is no item {id} in the trash"
+                )));
+            }
+            items.retain(|item| ids.contains(&item.id));
+        }
+        if let Some(deleted_before) = params.deleted_before {
+            items.retain(|item| item.deleted_at < deleted_before);
+        }
+        let size: u64 = items.iter().map(|item| item.size).sum();
+        if self.ctx.mode.guard(self.name())? {
+            return Ok(mode::dry_run(
+                self.name(),
+                json!({ "items": items, "size": size }),
+            ));
+        }
+        let removed = items.len();
+        tokio::task::spawn_blocking(move || items.iter().try_for_each(|item| trash.remove(item)))
+            .await
+            .map_err(mcp_core::ToolError::execution)?
+            .map_err(mcp_core::ToolError::execution)?;
+        Ok(vec![Content::text(
+            json!({ "removed": removed, "size": size }).to_string(),
+        )])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::Scratch;
+    use super::*;
+
+    fn trash(dir: &Scratch, max_age_secs: u64, max_bytes: u64) -> Trash {
+        Trash::new(TrashConfig {
+            enabled: true,
+            directory: dir.join("trash"),
+            max_age_secs,
+            max_bytes,
+        })
+    }
+
+    /// Put the file at `path` in the trash, as if it was deleted `age` ago.
+    fn put(trash: &Trash, path: &Path, age: chrono::Duration) -> TrashItem {
+        let mut item = trash.item(path).unwrap();
+        item.deleted_at -= age;
+        trash.put(PathJail::default(), &item).unwrap();
+        item
+    }
+
+    fn ids(trash: &Trash) -> Vec<String> {
+        let items = trash.list(&PathJail::default()).unwrap();
+        items.into_iter().map(|item| item.id).collect()
+    }
+
+    #[test]
+    fn restores_what_it_holds() {
+        let dir = Scratch::new("trash-restore");
+        let trash = trash(&dir, DEFAULT_MAX_AGE_SECS, DEFAULT_MAX_BYTES);
+        let path = dir.write("src/file", "file");
+        let item = put(&trash, &path, chrono::Duration::zero());
+        assert!(!path.exists());
+        assert_eq!((item.size, item.directory), (4, false));
+        assert_eq!(ids(&trash), [item.id.as_str()]);
+
+        // nothing is replaced by a restored item
+        dir.write("src/file", "new");
+        let error = trash
+            .restore(PathJail::default(), &item, &path)
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(ids(&trash), [item.id.as_str()]);
+
+        trash
+            .restore(PathJail::default(), &item, &dir.join("restored/file"))
+            .unwrap();
+        assert_eq!(dir.read("restored/file"), "file");
+        assert!(ids(&trash).is_empty());
+        assert!(!dir.join("trash").join(&item.id).exists());
+    }
+
+    #[test]
+    fn removes_items_past_the_retention_age() {
+        let dir = Scratch::new("trash-age");
+        let trash = trash(&dir, 60 * 60, DEFAULT_MAX_BYTES);
+        let old = put(&trash, &dir.write("old", ""), chrono::Duration::hours(2));
+        let recent = put(
+            &trash,
+            &dir.write("recent", ""),
+            chrono::Duration::minutes(30),
+        );
+        assert_eq!(ids(&trash), [recent.id.as_str()]);
+        assert!(!dir.join("trash").join(old.id).exists());
+    }
+
+    #[test]
+    fn removes_the_oldest_items_beyond_the_size_limit() {
+        let dir = Scratch::new("trash-size");
+        let trash = trash(&dir, DEFAULT_MAX_AGE_SECS, 10);
+        let first = put(
+            &trash,
+            &dir.write("first", "1234"),
+            chrono::Duration::minutes(2),
+        );
+        let second = put(
+            &trash,
+            &dir.write("second", "1234"),
+            chrono::Duration::minutes(1),
+        );
+        assert_eq!(ids(&trash), [second.id.as_str(), first.id.as_str()]);
+        let third = put(
+            &trash,
+            &dir.write("third", "1234"),
+            chrono::Duration::zero(),
+        );
+        assert_eq!(ids(&trash), [third.id.as_str(), second.id.as_str()]);
+        // the latest item is kept, however large
+        let large = put(
+            &trash,
+            &dir.write("large", [0; 11]),
+            chrono::Duration::zero(),
+        );
+        assert_eq!(ids(&trash), [large.id.as_str()]);
+    }
+
+    #[test]
+    fn lists_the_items_deleted_within_the_jail() {
+        let dir = Scratch::new("trash-jail");
+        let trash = trash(&dir, DEFAULT_MAX_AGE_SECS, DEFAULT_MAX_BYTES);
+        let inside = put(
+            &trash,
+            &dir.write("inside/file", ""),
+            chrono::Duration::zero(),
+        );
+        put(
+            &trash,
+            &dir.write("outside/file", ""),
+            chrono::Duration::zero(),
+        );
+        let jail = PathJail::new(&[dir.join("inside")], &[]).unwrap();
+        let listed: Vec<_> = trash
+            .list(&jail)
+            .unwrap()
+            .into_iter()
+            .map(|item| item.id)
+            .collect();
+        assert_eq!(listed, [inside.id.as_str()]);
+        assert!(trash.find(&jail, &inside.id).is_ok());
+        assert_eq!(trash.list_all().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,108 @@
+//! Walking of directory trees, shared by the tools which search them.
+
+use std::path::Path;
+
+use ignore::{Walk, WalkBuilder, overrides::OverrideBuilder};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::jail::PathJail;
+
+/// Which entries of a directory tree are visited.
+#[derive(Debug, Default, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct WalkOptions {
+    /// Globs of the files and directories to leave out, relative to the walked directory.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Also visit hidden files and directories.
+    #[serde(default)]
+    pub hidden: bool,
+    /// Also visit the files ignored by `.gitignore`, `.ignore` and git's exclude files.
+    #[serde(default)]
+    pub no_ignore: bool,
+    /// How deep to descend into the walked directory, without limit by default.
+    pub max_depth: Option<usize>,
+}
+
+/// Walk the tree at `root` in file name order, leaving out the entries the jail denies.
+/// When `include` has globs, only the files matching one of them are visited, directories
+/// are always visited.
+///
+/// Symlinks aren't followed, so the walk can't lead out of `root`.
+pub fn walk(
+    jail: &PathJail,
+    root: &Path,
+    include: &[String],
+    options: &WalkOptions,
+) -> Result<Walk, ignore::Error> {
+    Ok(builder(jail, root, include, options)?.build())
+}
+
+/// The builder of [`walk`], for walks which sort the entries of each directory otherwise.
+pub fn builder(
+    jail: &PathJail,
+    root: &Path,
+    include: &[String],
+    options: &WalkOptions,
+) -> Result<WalkBuilder, ignore::Error> {
+    let mut overrides = OverrideBuilder::new(root);
+    for glob in include {
+        overrides.add(glob)?;
+    }
+    for glob in &options.exclude {
+        overrides.add(&format!("!{glob}"))?;
+    }
+    let jail = jail.clone();
+    let ignore = !options.no_ignore;
+    let mut builder = WalkBuilder::new(root);
+    builder
+        .hidden(!options.hidden)
+        .ignore(ignore)
+        .git_ignore(ignore)
+        .git_global(ignore)
+        .git_exclude(ignore)
+        .parents(ignore)
+        .require_git(false)
+        .max_depth(options.max_depth)
+        .overrides(overrides.build()?)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .filter_entry(move |entry| !jail.is_denied(entry.path()));
+    Ok(builder)
+}
+
+/// Type of a directory entry, symlinks aren't followed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryType {
+    File,
+    Dir,
+    Symlink,
+    BlockDevice,
+    CharDevice,
+    Fifo,
+    Socket,
+    Other,
+}
+
+impl From<std::fs::FileType> for EntryType {
+    fn from(file_type: std::fs::FileType) -> Self {
+        use std::os::unix::fs::FileTypeExt;
+        if file_type.is_symlink() {
+            EntryType::Symlink
+        } else if file_type.is_dir() {
+            EntryType::Dir
+        } else if file_type.is_file() {
+            EntryType::File
+        } else if file_type.is_block_device() {
+            EntryType::BlockDevice
+        } else if file_type.is_char_device() {
+            EntryType::CharDevice
+        } else if file_type.is_fifo() {
+            EntryType::Fifo
+        } else if file_type.is_socket() {
+            EntryType::Socket
+        } else {
+            EntryType::Other
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Server-wide switch for tools which change the system.
+
+use mcp_core::{Content, ToolError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// Tools may change the system.
+    #[default]
+    ReadWrite,
+    /// Tools which change the system are refused.
+    ReadOnly,
+    /// Tools which change the system report what they would do instead.
+    DryRun,
+}
+
+impl Mode {
+    /// Guard a tool which changes the system.
+    ///
+    /// Fails in read-only mode, returns `true` when the tool should only report what it
+    /// would do with [`dry_run`].
+    pub fn guard(self, tool: &str) -> mcp_core::ToolResult<bool> {
+        match self {
+            Mode::ReadWrite => Ok(false),
+            Mode::ReadOnly => Err(ToolError::ExecutionError(format!(
+                "{tool} is refused, the server is in read-only mode"
+            ))),
+            Mode::DryRun => Ok(true),
+        }
+    }
+}
+
+/// Report what a tool would have done in dry-run mode.
+pub fn dry_run(tool: &str, plan: Value) -> Vec<Content> {
+    let report = serde_json::json!({
+        "dry_run": true,
+        "tool": tool,
+        "plan": plan,
+    });
+    vec![Content::text(
+        serde_json::to_string(&report).expect("invalid json"),
+    )]
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn guards_tools_by_mode() {
+        assert!(!Mode::ReadWrite.guard("fs.write").unwrap());
+        assert!(Mode::DryRun.guard("fs.write").unwrap());
+        match Mode::ReadOnly.guard("fs.write") {
+            Err(ToolError::ExecutionError(message)) => assert_eq!(
+                message,
+                "fs.write is refused, the server is in read-only mode"
+            ),
+            other => panic!("not refused: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn echoes_the_plan_in_dry_run_mode() {
+        let plan = json!({"path": "/tmp/file", "bytes": 3});
+        let contents = dry_run("fs.write", plan.clone());
+        let [Content::Text(text)] = contents.as_slice() else {
+            panic!("not a single text: {contents:?}");
+        };
+        let report: Value = serde_json::from_str(&text.text).unwrap();
+        assert_eq!(
+            report,
+            json!({"dry_run": true, "tool": "fs.write", "plan": plan})
+        );
+    }
+}
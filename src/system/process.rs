@@ -5,8 +5,11 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::embed;
-#[derive(Debug, Default)]
-pub struct Process {}
+use crate::system::mode::{self, Mode};
+#[derive(Debug, Default, Clone)]
+pub struct Process {
+    pub mode: Mode,
+}
 
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct ProcessCallParam {
@@ -51,6 +54,12 @@ impl TypedToolHandler for Process {
     }
 
     async fn call(&self, params: Self::Params) -> mcp_core::ToolResult<Vec<Content>> {
+        if self.mode.guard(self.name())? {
+            return Ok(mode::dry_run(
+                self.name(),
+                serde_json::to_value(&params).expect("invalid json"),
+            ));
+        }
         let output = tokio::process::Command::new(params.command)
             .args(params.args)
             .envs(params.envs)
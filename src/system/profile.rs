@@ -0,0 +1,81 @@
+//! Named sets of tools which can be served to a session.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// A named selection of tools.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Profile {
+    /// Names of the enabled tools, a trailing `*` matches any suffix (e.g. `fs.*`).
+    pub tools: Vec<String>,
+    /// Bearer tokens authorized to use this profile, anyone may use it when empty.
+    #[serde(default)]
+    pub tokens: Vec<String>,
+}
+
+impl Profile {
+    pub const READONLY: &str = "readonly";
+    pub const DEV: &str = "dev";
+    pub const FULL: &str = "full";
+
+    pub fn new<I, S>(tools: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Profile {
+            tools: tools.into_iter().map(Into::into).collect(),
+            tokens: Vec::new(),
+        }
+    }
+
+    /// Inspect the file system without changing it.
+    pub fn readonly() -> Self {
+        Self::new([
+            "fs.read",
+            "fs.read_lines",
+            "fs.search",
+            "fs.glob",
+            "fs.diff",
+            "fs.list_directory",
+            "fs.get_file_info",
+            "fs.read_link",
+            "fs.history",
+            "fs.trash_list",
+        ])
+    }
+
+    /// File system and process tools.
+    pub fn dev() -> Self {
+        Self::new(["fs.*", "process"])
+    }
+
+    /// Every tool.
+    pub fn full() -> Self {
+        Self::new(["*"])
+    }
+
+    /// The profiles available without any configuration.
+    pub fn builtin() -> HashMap<String, Profile> {
+        HashMap::from([
+            (Self::READONLY.to_string(), Self::readonly()),
+            (Self::DEV.to_string(), Self::dev()),
+            (Self::FULL.to_string(), Self::full()),
+        ])
+    }
+
+    pub fn allows(&self, tool: &str) -> bool {
+        self.tools
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => tool.starts_with(prefix),
+                None => tool == pattern,
+            })
+    }
+
+    /// Whether the `Authorization: Bearer` token grants access to this profile.
+    pub fn authorizes(&self, token: Option<&str>) -> bool {
+        self.tokens.is_empty() || token.is_some_and(|token| self.tokens.iter().any(|t| t == token))
+    }
+}